@@ -0,0 +1,78 @@
+//! Minimal stand-in UCI engine for trying out `tchess --engine`.
+//!
+//! It plays the first legal move it finds, after a short `info` line, and
+//! accepts but ignores every option.
+//!
+//! ```text
+//! cargo build --example uci_stub
+//! cargo run -- --vs-computer white --engine target/debug/examples/uci_stub
+//! ```
+
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use chess::{Board, ChessMove, MoveGen};
+
+fn main() {
+    let mut board = Board::default();
+    // a `go infinite` answers only once stopped
+    let mut pending: Option<Option<ChessMove>> = None;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                writeln!(out, "id name uci_stub").unwrap();
+                writeln!(out, "option name Hash type spin default 16 min 1 max 1024").unwrap();
+                writeln!(out, "option name Threads type spin default 1 min 1 max 1").unwrap();
+                writeln!(out, "option name Skill Level type spin default 20 min 0 max 20").unwrap();
+                writeln!(out, "uciok").unwrap();
+            }
+            Some("isready") => writeln!(out, "readyok").unwrap(),
+            Some("position") => board = position(&tokens[1..]).unwrap_or_default(),
+            Some("go") => {
+                let best = MoveGen::new_legal(&board).next();
+                if let Some(chess_move) = best {
+                    writeln!(out, "info depth 1 score cp 0 nodes 1 pv {chess_move}").unwrap();
+                }
+                if tokens.contains(&"infinite") {
+                    pending = Some(best);
+                } else {
+                    bestmove(&mut out, best);
+                }
+            }
+            Some("stop") => {
+                if let Some(best) = pending.take() {
+                    bestmove(&mut out, best);
+                }
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+        out.flush().unwrap();
+    }
+}
+
+fn bestmove(out: &mut impl Write, best: Option<ChessMove>) {
+    match best {
+        Some(chess_move) => writeln!(out, "bestmove {chess_move}").unwrap(),
+        None => writeln!(out, "bestmove (none)").unwrap(),
+    }
+}
+
+/// Reads the arguments of a `position` command.
+fn position(tokens: &[&str]) -> Option<Board> {
+    let moves_at = tokens.iter().position(|t| *t == "moves").unwrap_or(tokens.len());
+    let mut board = match *tokens.first()? {
+        "startpos" => Board::default(),
+        "fen" => Board::from_str(&tokens[1..moves_at].join(" ")).ok()?,
+        _ => return None,
+    };
+    for text in tokens.iter().skip(moves_at + 1) {
+        board = board.make_move_new(ChessMove::from_str(text).ok()?);
+    }
+    Some(board)
+}
@@ -0,0 +1,205 @@
+//! Analysis mode: an engine evaluating whatever position is on the board.
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use anyhow::Result;
+use chess::{Board, ChessMove, Color};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Color as TermColor,
+    widgets::Widget,
+};
+
+use crate::engine::search::{SearchInfo, MATE};
+use crate::engine::{Backend, Engine};
+use crate::event::Event;
+use crate::history::GameHistory;
+use crate::san;
+use crate::uci::{Go, Info, Score, UciEngine};
+
+/// One of the best lines found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub depth: u32,
+    /// evaluation from White's point of view
+    pub score: Score,
+    /// principal variation, best move first
+    pub pv: Vec<ChessMove>,
+}
+
+impl Line {
+    /// Converts a line of the built-in search, played from `board`.
+    pub fn from_search(info: &SearchInfo, board: &Board) -> Self {
+        // mates are scored relative to MATE, counted in plies
+        let score = if info.score.abs() >= MATE - 1000 {
+            let plies = MATE - info.score.abs();
+            Score::Mate(info.score.signum() * (plies + 1) / 2)
+        } else {
+            Score::Cp(info.score)
+        };
+        Self { depth: info.depth.into(), score: white_view(score, board), pv: info.pv.clone() }
+    }
+
+    /// Converts a UCI `info` line about `board`, if it carries a score.
+    pub fn from_uci(info: &Info, board: &Board) -> Option<Self> {
+        let score = white_view(info.score?, board);
+        Some(Self { depth: info.depth.unwrap_or(0), score, pv: info.pv.clone() })
+    }
+
+    /// The variation in numbered SAN, e.g. `12... Nf6 13. e5`, played from `board`.
+    pub fn san(&self, board: &Board, fullmove: u32) -> String {
+        let mut position = *board;
+        let mut sans = Vec::new();
+        for &chess_move in &self.pv {
+            if !position.legal(chess_move) {
+                break;
+            }
+            sans.push(san::to_san(&position, chess_move));
+            position = position.make_move_new(chess_move);
+        }
+        san::numbered_pairs(&sans, board.side_to_move(), fullmove).join(" ")
+    }
+}
+
+fn white_view(score: Score, board: &Board) -> Score {
+    match (board.side_to_move(), score) {
+        (Color::White, _) => score,
+        (Color::Black, Score::Cp(cp)) => Score::Cp(-cp),
+        (Color::Black, Score::Mate(moves)) => Score::Mate(-moves),
+    }
+}
+
+/// Formats `score` as pawns, e.g. `+0.35`, or as a mate, e.g. `#-3`.
+pub fn format_score(score: Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("{:+.2}", f64::from(cp) / 100.0),
+        Score::Mate(moves) => format!("#{moves}"),
+    }
+}
+
+/// White's share of an evaluation bar showing `score`, from 0 to 1.
+pub fn white_share(score: Score) -> f64 {
+    match score {
+        // the usual logistic mapping from centipawns to winning chances
+        Score::Cp(cp) => 1.0 / (1.0 + 10f64.powf(-f64::from(cp) / 400.0)),
+        Score::Mate(moves) if moves > 0 => 1.0,
+        Score::Mate(_) => 0.0,
+    }
+}
+
+/// Exploring positions away from the played game while an engine evaluates them.
+#[derive(Debug)]
+pub struct Analysis {
+    /// the played game, put aside until analysis ends
+    pub played: GameHistory,
+    /// whether the clock was running when analysis began
+    pub paused_clock: bool,
+    /// best lines of the position on the board, best first
+    pub lines: Vec<Line>,
+}
+
+/// Engine used for analysis, kept apart from the computer opponent.
+#[derive(Debug)]
+pub struct Analyser {
+    pub backend: Backend,
+    /// number of lines to show
+    pub lines: usize,
+}
+
+/// How to start the analysis engine, which is only done once analysis is asked for.
+#[derive(Debug, Clone)]
+pub struct AnalyserSetup {
+    /// UCI engine to analyse with instead of the built-in one
+    pub engine: Option<PathBuf>,
+    /// options set on the UCI engine
+    pub options: Vec<(String, String)>,
+    /// number of lines to show
+    pub lines: usize,
+    /// where the engine's results go
+    pub events: mpsc::Sender<Event>,
+}
+
+impl AnalyserSetup {
+    /// Starts an engine of its own for analysis, so it never disturbs the opponent's search.
+    pub fn start(&self) -> Result<Analyser> {
+        let backend = match &self.engine {
+            Some(path) => Backend::Uci(UciEngine::spawn(path, &[], &self.options, self.events.clone(), Event::UciAnalysis)?),
+            None => Backend::Native(Engine::spawn(self.events.clone())),
+        };
+        Analyser::new(backend, self.lines)
+    }
+}
+
+impl Analyser {
+    /// Analyses with `backend`, asking UCI engines for `lines` lines when they can.
+    pub fn new(mut backend: Backend, lines: usize) -> Result<Self> {
+        if let Backend::Uci(engine) = &mut backend {
+            if engine.has_option("MultiPV") {
+                engine.set_option("MultiPV", &lines.to_string())?;
+            }
+        }
+        Ok(Self { backend, lines })
+    }
+
+    /// Starts analysing the current position of `history` until stopped.
+    pub fn go(&mut self, history: &GameHistory) -> Result<()> {
+        match &mut self.backend {
+            Backend::Native(engine) => {
+                let positions = (0..history.current()).map(|ply| history.position_at(ply).get_hash()).collect();
+                engine.analyse(history.position(), positions, self.lines);
+                Ok(())
+            }
+            Backend::Uci(engine) => {
+                let moves: Vec<_> = history.plies()[..history.current()].iter().map(|ply| ply.chess_move).collect();
+                engine.go(&history.fen_at(0), &moves, Go::Infinite)
+            }
+        }
+    }
+
+    /// Stops analysing.
+    pub fn stop(&mut self) -> Result<()> {
+        match &mut self.backend {
+            Backend::Native(engine) => {
+                engine.stop();
+                Ok(())
+            }
+            Backend::Uci(engine) => engine.stop(),
+        }
+    }
+}
+
+/// Vertical bar filled with White's share, from 0 to 1, from White's end of the board.
+pub struct EvalBar {
+    pub white_share: f64,
+    /// whether Black is at the bottom, filling the bar with Black's share instead
+    pub flipped: bool,
+    /// colours of White's and Black's shares, those of their pieces
+    pub white: TermColor,
+    pub black: TermColor,
+}
+
+impl Widget for EvalBar {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // eighths of a cell, drawn with the lower block glyphs
+        const BLOCKS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+        let white_share = self.white_share.clamp(0.0, 1.0);
+        let (share, fg, bg) = if self.flipped {
+            (1.0 - white_share, self.black, self.white)
+        } else {
+            (white_share, self.white, self.black)
+        };
+        let mut filled = (share * f64::from(area.height) * 8.0).round() as u16;
+        for y in (area.top()..area.bottom()).rev() {
+            let eighths = filled.min(8);
+            filled -= eighths;
+            for x in area.left()..area.right() {
+                buf.get_mut(x, y)
+                    .set_symbol(BLOCKS[usize::from(eighths)])
+                    .set_fg(fg)
+                    .set_bg(bg);
+            }
+        }
+    }
+}
@@ -1,6 +1,59 @@
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use crate::game::{MyGame, MyGameState};
+use chess::{ChessMove, Color, Piece, Square};
+use ratatui::layout::Rect;
+
+use crate::analysis::{Analyser, AnalyserSetup, Analysis, Line};
+use crate::clock::{Clock, TimeControl};
+use crate::engine::eval::evaluate;
+use crate::engine::search::SearchInfo;
+use crate::engine::{Backend, Computer, Limits};
+use crate::game::{MyGame, MyGameState, Orientation};
+use crate::history::GameHistory;
+use crate::keymap::Keymap;
+use crate::legality;
+use crate::outcome::{color_name, Outcome};
+use crate::pgn::{self, PgnGame};
+use crate::theme::{ColorDepth, Palette, Theme};
+use crate::uci::UciMessage;
+use crate::viewer::Viewer;
+
+/// Something a player can do besides moving, from a key or a button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    /// claim a draw by threefold repetition or the fifty-move rule
+    ClaimDraw,
+}
+
+/// Start of the status explaining why a move was refused, which the next
+/// move clears.
+const ILLEGAL: &str = "Illegal move: ";
+
+/// Status for picking up a piece while the computer is to move, which the
+/// computer's move clears.
+const WAITING: &str = "Waiting for the engine to move";
+
+/// Pieces a pawn can promote to, in the order the promotion popup shows them.
+pub const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+/// A pawn move waiting for the player to pick the piece it promotes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Promotion {
+    pub from: Square,
+    pub to: Square,
+    /// index into [`PROMOTION_PIECES`] of the highlighted piece
+    pub choice: usize,
+    /// the popup where it was last drawn
+    pub area: Rect,
+    /// each piece's tile where it was last drawn, for mouse clicks
+    pub tiles: Vec<(Rect, Piece)>,
+}
 
 #[derive(Debug, Default)]
 pub struct App {
@@ -10,6 +63,48 @@ pub struct App {
     pub game: MyGame,
     /// state
     pub game_state: MyGameState,
+    /// moves played, for undo and redo
+    pub history: GameHistory,
+    /// message shown in the status panel
+    pub status: Option<String>,
+    /// where to save the game as PGN
+    pub pgn_out: Option<PathBuf>,
+    /// games being replayed from a PGN file
+    pub viewer: Option<Viewer>,
+    /// chess clock, when playing with a time control
+    pub clock: Option<Clock>,
+    /// how the game ended, if it did
+    pub outcome: Option<Outcome>,
+    /// whether the result banner is shown over the board
+    pub banner: bool,
+    /// side whose draw offer awaits an answer
+    pub draw_offer: Option<Color>,
+    /// whether resigning waits for a second press to confirm it
+    pub confirm_resign: bool,
+    /// action buttons where they were last drawn
+    pub buttons: Vec<(Rect, GameAction)>,
+    /// moves of the move list where they were last drawn, with the ply each leads to
+    pub move_buttons: Vec<(Rect, usize)>,
+    /// promotion waiting for its piece to be picked
+    pub promotion: Option<Promotion>,
+    /// which side is drawn at the bottom of the board
+    pub orientation: Orientation,
+    /// colour themes to switch between
+    pub themes: Vec<Theme>,
+    /// index of the theme in use
+    pub theme: usize,
+    /// colours the terminal can show
+    pub color_depth: ColorDepth,
+    /// actions bound to keys
+    pub keymap: Keymap,
+    /// computer opponent, when not playing hotseat
+    pub computer: Option<Computer>,
+    /// analysis mode, while exploring away from the played game
+    pub analysis: Option<Analysis>,
+    /// engine for analysis mode, once started
+    pub analyser: Option<Analyser>,
+    /// how to start the engine for analysis mode, when there is one
+    pub analyser_setup: Option<AnalyserSetup>,
 }
 
 impl App {
@@ -19,10 +114,755 @@ impl App {
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    ///
+    /// Ends the game when the side to move runs out of time.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        let Some(clock) = &mut self.clock else {
+            return;
+        };
+        if let Some(color) = clock.flagged(now) {
+            clock.stop(now);
+            self.promotion = None;
+            let outcome = Outcome::on_time(color, &self.game.0.current_position());
+            self.status = Some(outcome.describe());
+            self.outcome = Some(outcome);
+            self.banner = true;
+            self.wake_computer();
+        }
+    }
+
+    /// Plays with `control` on both clocks.
+    pub fn set_time_control(&mut self, control: TimeControl) {
+        self.clock = Some(Clock::new(control));
+    }
+
+    /// Plays `chess_move` for the player if it is legal in the current position.
+    ///
+    /// Returns whether the move was made.
+    pub fn try_move(&mut self, chess_move: ChessMove) -> bool {
+        if !self.human_to_move() {
+            return false;
+        }
+        if !self.make_move(chess_move) {
+            return false;
+        }
+        if self.status.as_deref().is_some_and(|status| status.starts_with(ILLEGAL)) {
+            self.status = None;
+        }
+        true
+    }
+
+    /// Tells the player in the status panel why the piece on `from` cannot
+    /// move to `to`.
+    pub fn explain_illegal(&mut self, from: Square, to: Square) {
+        let board = self.game.0.current_position();
+        if let Some(reason) = legality::why_illegal(&board, from, to) {
+            self.status = Some(format!("{ILLEGAL}{reason}"));
+        }
+    }
+
+    /// Asks which piece to promote to, if the pawn on `from` can promote on
+    /// `to` and the player may move it.
+    ///
+    /// Returns whether the promotion popup was opened.
+    pub fn start_promotion(&mut self, from: Square, to: Square) -> bool {
+        let legal = self.game.0.current_position().legal(ChessMove::new(from, to, Some(Piece::Queen)));
+        if !legal || !self.human_to_move() {
+            return false;
+        }
+        self.promotion = Some(Promotion { from, to, choice: 0, area: Rect::default(), tiles: Vec::new() });
+        true
+    }
+
+    /// Plays the waiting promotion with `piece`, closing the popup.
+    pub fn promote(&mut self, piece: Piece) {
+        let Some(promotion) = self.promotion.take() else {
+            return;
+        };
+        self.game_state.deselect();
+        self.try_move(ChessMove::new(promotion.from, promotion.to, Some(piece)));
+    }
+
+    /// Closes the promotion popup without moving, leaving the pawn selected.
+    pub fn cancel_promotion(&mut self) {
+        self.promotion = None;
+    }
+
+    /// Plays the move found by search `id` of the built-in engine, unless it was abandoned.
+    pub fn computer_move(&mut self, id: u64, chess_move: ChessMove) {
+        let Some(Computer { backend: Backend::Native(engine), .. }) = &mut self.computer else {
+            return;
+        };
+        if engine.accept(id) {
+            self.make_move(chess_move);
+        }
+    }
+
+    /// Handles output of the UCI engine, playing its best move.
+    pub fn uci_message(&mut self, message: UciMessage) {
+        let Some(Computer { backend: Backend::Uci(engine), .. }) = &mut self.computer else {
+            return;
+        };
+        if !engine.accept(&message) {
+            return;
+        }
+        let name = engine.name.clone();
+        match message {
+            UciMessage::BestMove(Some(chess_move)) => {
+                if !self.make_move(chess_move) {
+                    self.status = Some(format!("{name} played the illegal move {chess_move}"));
+                }
+            }
+            UciMessage::Exited => {
+                self.status = Some(format!("{name} exited, continuing without it"));
+                self.computer = None;
+            }
+            UciMessage::BestMove(None) | UciMessage::Info(_) => {}
+        }
+    }
+
+    /// Shows the lines found by analysis `id` of the built-in engine, unless it was abandoned.
+    pub fn analysis_lines(&mut self, id: u64, infos: Vec<SearchInfo>) {
+        let (Some(analysis), Some(Analyser { backend: Backend::Native(engine), .. })) = (&mut self.analysis, &self.analyser)
+        else {
+            return;
+        };
+        if engine.current(id) {
+            let board = self.history.position();
+            analysis.lines = infos.iter().map(|info| Line::from_search(info, &board)).collect();
+        }
+    }
+
+    /// Handles output of the UCI engine analysing, keeping its latest lines.
+    pub fn uci_analysis(&mut self, message: UciMessage) {
+        let Some(Analyser { backend: Backend::Uci(engine), .. }) = &mut self.analyser else {
+            return;
+        };
+        if !engine.accept(&message) {
+            return;
+        }
+        match message {
+            UciMessage::Info(info) => {
+                let Some(analysis) = &mut self.analysis else {
+                    return;
+                };
+                let Some(line) = Line::from_uci(&info, &self.history.position()) else {
+                    return;
+                };
+                // each `multipv` replaces its line, the others keep their last depth meanwhile
+                let index = info.multipv.saturating_sub(1);
+                match analysis.lines.get_mut(index) {
+                    Some(old) => *old = line,
+                    None => analysis.lines.push(line),
+                }
+            }
+            UciMessage::Exited => {
+                self.status = Some(format!("{} exited, analysis is unavailable", engine.name));
+                self.analyser = None;
+            }
+            UciMessage::BestMove(_) => {}
+        }
+    }
+
+    /// Whether it is the computer's turn.
+    pub fn computer_to_move(&self) -> bool {
+        self.computer.as_ref().is_some_and(|computer| computer.color == self.game.0.side_to_move())
+    }
+
+    /// Whether the player may move the side to move's pieces, which in
+    /// analysis they always may.
+    pub fn human_to_move(&self) -> bool {
+        !self.computer_to_move() || self.analysis.is_some()
+    }
+
+    /// Tells the player that the pieces they reached for are the computer's.
+    pub fn wait_for_computer(&mut self) {
+        self.status = Some(WAITING.to_owned());
+    }
+
+    /// Lets the computer search for a move if it is its turn in an unfinished game.
+    fn wake_computer(&mut self) {
+        let over = self.outcome.is_some()
+            || self.game.0.result().is_some()
+            || self.viewer.is_some()
+            || self.analysis.is_some();
+        let to_move = self.computer_to_move();
+        let Some(computer) = &mut self.computer else {
+            return;
+        };
+        let result = if over || !to_move {
+            computer.stop()
+        } else if !computer.thinking() {
+            computer.go(&self.history, self.clock.as_ref())
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            self.status = Some(format!("{e:#}"));
+        }
+    }
+
+    /// Plays `chess_move` if it is legal in the current position.
+    ///
+    /// In analysis mode the move only changes the position explored.
+    fn make_move(&mut self, chess_move: ChessMove) -> bool {
+        let exploring = self.analysis.is_some();
+        if (self.outcome.is_some() && !exploring) || !self.game.0.current_position().legal(chess_move) {
+            return false;
+        }
+        let mover = self.game.0.side_to_move();
+        if !self.game.0.make_move(chess_move) {
+            return false;
+        }
+        self.history.push(chess_move);
+        // the selection's destinations are those of the position before
+        self.game_state.deselect();
+        self.confirm_resign = false;
+        if self.status.as_deref() == Some(WAITING) {
+            self.status = None;
+        }
+        // moving instead of answering a draw offer declines it
+        if self.draw_offer.is_some_and(|offerer| offerer != mover && !exploring) {
+            self.status = Some(format!("{} declines the draw", color_name(mover)));
+            self.draw_offer = None;
+        }
+        if !exploring {
+            self.check_outcome();
+        }
+
+        // the clocks start once the first move is on the board, and stop with the game
+        if let Some(clock) = self.clock.as_mut().filter(|_| !exploring) {
+            let now = Instant::now();
+            match clock.running() {
+                _ if self.outcome.is_some() => clock.stop(now),
+                Some(_) => clock.press(now),
+                None => clock.start(self.game.0.side_to_move(), now),
+            }
+        }
+        self.wake_computer();
+        self.wake_analyser();
+        true
+    }
+
+    /// Ends the game if its last move finished it, showing the result banner.
+    fn check_outcome(&mut self) {
+        self.set_outcome(Outcome::of_game(&self.game.0, &self.history));
+    }
+
+    /// Ends the game with `outcome`, or keeps it going with `None`.
+    fn set_outcome(&mut self, outcome: Option<Outcome>) {
+        self.outcome = outcome;
+        if let Some(outcome) = &self.outcome {
+            self.status = Some(outcome.describe());
+            self.banner = true;
+        }
+    }
+
+    /// Side drawn at the bottom of the board.
+    pub fn bottom_side(&self) -> Color {
+        match self.orientation {
+            Orientation::White => Color::White,
+            Orientation::Black => Color::Black,
+            Orientation::SideToMove => self.game.0.side_to_move(),
+        }
+    }
+
+    /// Colours of the theme in use, as the terminal can show them.
+    pub fn palette(&self) -> Palette {
+        self.themes
+            .get(self.theme)
+            .map_or_else(Palette::default, |theme| theme.colors)
+            .reduce(self.color_depth)
+    }
+
+    /// Switches to the next theme, naming it in the status panel.
+    pub fn next_theme(&mut self) {
+        if self.themes.is_empty() {
+            return;
+        }
+        self.theme = (self.theme + 1) % self.themes.len();
+        self.status = Some(format!("Theme: {}", self.themes[self.theme].name));
+    }
+
+    /// Turns the board around, keeping it that way from then on.
+    pub fn flip_board(&mut self) {
+        self.orientation = match self.bottom_side() {
+            Color::White => Orientation::Black,
+            Color::Black => Orientation::White,
+        };
+    }
+
+    /// Side the actions are taken for: the player against the computer, or
+    /// the side to move in hotseat.
+    pub fn player(&self) -> Color {
+        match &self.computer {
+            Some(computer) => !computer.color,
+            None => self.game.0.side_to_move(),
+        }
+    }
+
+    /// Actions open to the player right now.
+    pub fn available_actions(&self) -> Vec<GameAction> {
+        if self.outcome.is_some() || self.viewer.is_some() || self.analysis.is_some() {
+            return Vec::new();
+        }
+        let player = self.player();
+        let mut actions = vec![GameAction::Resign];
+        match self.draw_offer {
+            Some(offerer) if offerer != player && self.game.0.side_to_move() == player => {
+                actions.extend([GameAction::AcceptDraw, GameAction::DeclineDraw]);
+            }
+            Some(_) => {}
+            None => actions.push(GameAction::OfferDraw),
+        }
+        actions.push(GameAction::ClaimDraw);
+        actions
+    }
+
+    /// Takes `action` for the player, if it is open to them.
+    pub fn perform(&mut self, action: GameAction) {
+        if action != GameAction::Resign {
+            self.confirm_resign = false;
+        }
+        if !self.available_actions().contains(&action) {
+            return;
+        }
+        let player = self.player();
+        let mut claimed = None;
+        match action {
+            GameAction::Resign if !self.confirm_resign => {
+                self.confirm_resign = true;
+                self.status = Some("Resign? Press again to confirm".to_owned());
+            }
+            GameAction::Resign => {
+                self.confirm_resign = false;
+                self.game.0.resign(player);
+            }
+            GameAction::OfferDraw => {
+                self.game.0.offer_draw(player);
+                self.draw_offer = Some(player);
+                self.answer_draw_offer();
+            }
+            GameAction::AcceptDraw => {
+                self.game.0.accept_draw();
+            }
+            GameAction::DeclineDraw => {
+                self.draw_offer = None;
+                self.status = Some(format!("{} declines the draw", color_name(player)));
+            }
+            GameAction::ClaimDraw => {
+                claimed = Outcome::claimed_draw(&self.history);
+                if claimed.is_none() {
+                    self.status = Some(
+                        "No draw to claim: that needs a threefold repetition or fifty moves without a capture or pawn move"
+                            .to_owned(),
+                    );
+                }
+            }
+        }
+        match claimed {
+            Some(outcome) => self.set_outcome(Some(outcome)),
+            None => self.check_outcome(),
+        }
+        if self.outcome.is_some() {
+            self.draw_offer = None;
+            if let Some(clock) = &mut self.clock {
+                clock.stop(Instant::now());
+            }
+            self.wake_computer();
+        }
+    }
+
+    /// Lets the computer answer a draw offer at once, accepting only when
+    /// it stands clearly worse.
+    fn answer_draw_offer(&mut self) {
+        let Some(computer) = &self.computer else {
+            return;
+        };
+        let board = self.game.0.current_position();
+        let score = if board.side_to_move() == computer.color { evaluate(&board) } else { -evaluate(&board) };
+        if score < -200 {
+            self.game.0.accept_draw();
+        } else {
+            self.status = Some("The computer declines the draw".to_owned());
+        }
+        self.draw_offer = None;
+    }
+
+    /// Starts another game with the clocks reset.
+    ///
+    /// A rematch replays the same starting position with the colours swapped,
+    /// otherwise the game starts from the standard position.
+    pub fn new_game(&mut self, rematch: bool) {
+        self.history = if rematch { self.history.restarted() } else { GameHistory::default() };
+        if let Some(computer) = self.computer.as_mut().filter(|_| rematch) {
+            computer.color = !computer.color;
+            // a player watching from their own side keeps doing so with the other colour
+            let was_player = computer.color;
+            if self.orientation != Orientation::SideToMove && self.bottom_side() == was_player {
+                self.flip_board();
+            }
+        }
+        self.status = None;
+        self.reset();
+    }
+
+    /// Clears the result and clocks of the game in `history`, which has just begun.
+    fn reset(&mut self) {
+        self.outcome = None;
+        self.banner = false;
+        self.draw_offer = None;
+        if let Some(clock) = &mut self.clock {
+            *clock = Clock::new(clock.control);
+        }
+        self.sync_history();
+        self.check_outcome();
+    }
+
+    /// Replays the finished game in the viewer.
+    pub fn review(&mut self) {
+        let game = PgnGame::from_history(&self.history, self.outcome.as_ref());
+        let end = game.moves.len();
+        self.banner = false;
+        self.view(vec![game], 0);
+        self.jump_to(end);
+    }
+
+    /// Leaves the replay of a finished game, back to its result.
+    pub fn end_review(&mut self) {
+        self.viewer = None;
+        self.jump_to(self.history.plies().len());
+        self.banner = self.outcome.is_some();
+    }
+
+    /// Plays against the computer, which takes `color` and searches within `limits`.
+    pub fn set_computer(&mut self, color: Color, limits: Limits, backend: Backend) {
+        self.computer = Some(Computer { color, limits, backend });
+        self.wake_computer();
+    }
+
+    /// Takes back the last move.
+    ///
+    /// Against the computer this goes back to the player's previous turn.
+    pub fn undo(&mut self) {
+        if !self.history.undo() {
+            return;
+        }
+        if let Some(computer) = &self.computer {
+            let side = self.history.position().side_to_move();
+            if side == computer.color && self.viewer.is_none() && self.analysis.is_none() {
+                self.history.undo();
+            }
+        }
+        self.sync_history();
+    }
+
+    /// Replays the last move taken back.
+    pub fn redo(&mut self) {
+        if self.history.redo() {
+            self.sync_history();
+        }
+    }
+
+    /// Goes to `ply`, where 0 is the starting position.
+    pub fn jump_to(&mut self, ply: usize) {
+        if self.history.jump(ply) {
+            self.sync_history();
+        }
+    }
+
+    /// Rebuilds the game after the current ply of the history changed.
+    ///
+    /// Rebuilding drops a pending draw offer.
+    fn sync_history(&mut self) {
+        self.game = self.history.game();
+        self.game_state.deselect();
+        self.promotion = None;
+        self.draw_offer = None;
+        self.confirm_resign = false;
+        if let Some(clock) = &mut self.clock {
+            if clock.running().is_some() {
+                clock.start(self.game.0.side_to_move(), Instant::now());
+            }
+        }
+        if let Some(computer) = &mut self.computer {
+            if let Err(e) = computer.stop() {
+                self.status = Some(format!("{e:#}"));
+            }
+        }
+        self.wake_computer();
+        self.wake_analyser();
+    }
+
+    /// Enters analysis mode, or goes back to the played game.
+    ///
+    /// The played game is put aside meanwhile, with its clock paused.
+    pub fn toggle_analysis(&mut self) {
+        if let Some(analysis) = self.analysis.take() {
+            if let Some(analyser) = &mut self.analyser {
+                if let Err(e) = analyser.stop() {
+                    self.status = Some(format!("{e:#}"));
+                }
+            }
+            self.history = analysis.played;
+            if let Some(clock) = self.clock.as_mut().filter(|_| analysis.paused_clock) {
+                clock.start(self.history.position().side_to_move(), Instant::now());
+            }
+            self.status = None;
+            self.banner = self.outcome.is_some() && self.viewer.is_none();
+            self.sync_history();
+            return;
+        }
+        // the engine starts the first time it is needed, or again after it exited
+        if self.analyser.is_none() {
+            match self.analyser_setup.as_ref().map(AnalyserSetup::start) {
+                Some(Ok(analyser)) => self.analyser = Some(analyser),
+                Some(Err(e)) => {
+                    self.status = Some(format!("{e:#}"));
+                    return;
+                }
+                None => {
+                    self.status = Some("No engine to analyse with".to_owned());
+                    return;
+                }
+            }
+        }
+        let mut paused_clock = false;
+        if let Some(clock) = &mut self.clock {
+            paused_clock = clock.running().is_some();
+            clock.stop(Instant::now());
+        }
+        self.analysis = Some(Analysis { played: self.history.clone(), paused_clock, lines: Vec::new() });
+        self.status = Some("Analysing, moves no longer count for the game until `a` is pressed again".to_owned());
+        self.sync_history();
+    }
+
+    /// Restarts the analysis on the position now on the board.
+    fn wake_analyser(&mut self) {
+        let (Some(analysis), Some(analyser)) = (&mut self.analysis, &mut self.analyser) else {
+            return;
+        };
+        analysis.lines.clear();
+        if let Err(e) = analyser.go(&self.history) {
+            self.status = Some(format!("{e:#}"));
+        }
+    }
+
+    /// Starts a new game from the position in `fen`.
+    ///
+    /// In analysis mode the position is only explored.
+    pub fn load_fen(&mut self, fen: &str) -> anyhow::Result<()> {
+        self.history = GameHistory::from_fen(fen)?;
+        match self.analysis {
+            Some(_) => self.sync_history(),
+            None => self.reset(),
+        }
+        Ok(())
+    }
+
+    /// Handles text pasted into the terminal, loading it as a FEN position.
+    pub fn paste(&mut self, text: &str) {
+        if self.viewer.is_some() && self.analysis.is_none() {
+            return;
+        }
+        self.status = Some(match self.load_fen(text.trim()) {
+            Ok(()) => "Loaded position from FEN".to_owned(),
+            Err(e) => format!("Not a valid FEN: {e}"),
+        });
+    }
+
+    /// Shows the FEN of the current position in the status panel.
+    pub fn show_fen(&mut self) {
+        self.status = Some(self.history.fen());
+    }
+
+    /// Opens `games` for replaying, showing the one at `index`.
+    pub fn view(&mut self, games: Vec<PgnGame>, index: usize) {
+        self.viewer = Some(Viewer { games, index: 0 });
+        self.show_game(index);
+    }
+
+    /// Shows game `index` of the viewer at its starting position.
+    pub fn show_game(&mut self, index: usize) {
+        let Some(viewer) = &mut self.viewer else {
+            return;
+        };
+        if index >= viewer.games.len() {
+            return;
+        }
+        viewer.index = index;
+        let game = viewer.game();
+        self.history = game
+            .tag("FEN")
+            .and_then(|fen| GameHistory::from_fen(fen).ok())
+            .unwrap_or_else(|| GameHistory::new(game.start));
+        for pgn_move in &game.moves {
+            self.history.push(pgn_move.chess_move);
+        }
+        self.history.jump(0);
+        self.sync_history();
+    }
+
+    /// Saves the game as PGN, to `pgn_out` or a timestamped file.
+    pub fn save_pgn(&mut self) {
+        let path = self.pgn_out.clone().unwrap_or_else(|| pgn::default_file_name().into());
+        self.status = Some(match self.write_pgn(&path) {
+            Ok(()) => format!("Saved game to {}", path.display()),
+            Err(e) => format!("{e:#}"),
+        });
+    }
+
+    /// Writes the game as PGN to `path`, leaving out any analysis.
+    pub fn write_pgn(&self, path: &Path) -> anyhow::Result<()> {
+        match &self.analysis {
+            Some(analysis) => pgn::save(&analysis.played.game(), &analysis.played, self.outcome.as_ref(), path),
+            None => pgn::save(&self.game, &self.history, self.outcome.as_ref(), path),
+        }
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.should_quit = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+    use std::sync::mpsc;
+
+    use crate::engine::Engine;
+    use crate::event::Event;
+    use crate::outcome::Reason;
+
+    /// A game against the computer playing `color`, seen with `orientation`.
+    fn versus(color: Color, orientation: Orientation) -> App {
+        let mut app = App { orientation, ..App::default() };
+        let limits = Limits { depth: 1, movetime: None };
+        app.set_computer(color, limits, Backend::Native(Engine::spawn(mpsc::channel().0)));
+        app
+    }
+
+    fn play(app: &mut App, moves: &[&str]) {
+        for uci in moves {
+            assert!(app.try_move(ChessMove::from_str(uci).unwrap()), "{uci}");
+        }
+    }
+
+    #[test]
+    fn fifty_move_claims_count_the_fen_halfmove_clock() {
+        let mut app = App::default();
+        app.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 60").unwrap();
+        assert!(!app.history.can_claim_draw());
+        app.perform(GameAction::ClaimDraw);
+        assert!(app.outcome.is_none());
+
+        play(&mut app, &["a1a2"]);
+        app.perform(GameAction::ClaimDraw);
+        let outcome = app.outcome.expect("the claim was refused");
+        assert_eq!((outcome.winner, outcome.reason), (None, Reason::FiftyMoves));
+    }
+
+    #[test]
+    fn threefold_repetition_can_be_claimed() {
+        let mut app = App::default();
+        play(&mut app, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert!(!app.history.can_claim_draw());
+        play(&mut app, &["f6g8"]);
+        app.perform(GameAction::ClaimDraw);
+        assert_eq!(app.outcome.map(|outcome| outcome.reason), Some(Reason::Repetition));
+    }
+
+    /// Lets the built-in engine, searching for the computer, make its move.
+    /// Moves of searches given up meanwhile are ignored as in the game.
+    fn reply(app: &mut App, events: &mpsc::Receiver<Event>) {
+        while app.computer_to_move() {
+            let event = events.recv_timeout(std::time::Duration::from_secs(10)).expect("the computer never moved");
+            if let Event::Computer(id, chess_move) = event {
+                app.computer_move(id, chess_move);
+            }
+        }
+    }
+
+    #[test]
+    fn undo_takes_back_the_computer_reply_too() {
+        let (sender, events) = mpsc::channel();
+        let mut app = App::default();
+        let limits = Limits { depth: 1, movetime: None };
+        app.set_computer(Color::Black, limits, Backend::Native(Engine::spawn(sender)));
+        play(&mut app, &["e2e4"]);
+        reply(&mut app, &events);
+        assert_eq!(app.history.current(), 2);
+
+        // back to the player's previous turn, not to the computer's
+        app.undo();
+        assert_eq!(app.history.current(), 0);
+        assert!(!app.computer_to_move());
+
+        // while the computer thinks, only the player's move is taken back
+        play(&mut app, &["d2d4"]);
+        assert!(app.computer_to_move());
+        app.undo();
+        assert_eq!(app.history.current(), 0);
+        play(&mut app, &["d2d4"]);
+        reply(&mut app, &events);
+        play(&mut app, &["c2c4"]);
+        app.undo();
+        assert_eq!(app.history.current(), 2);
+        assert!(!app.computer_to_move());
+    }
+
+    #[test]
+    fn undo_takes_back_single_plies_in_hotseat() {
+        let mut app = App::default();
+        play(&mut app, &["e2e4", "e7e5"]);
+        app.undo();
+        assert_eq!(app.history.current(), 1);
+        app.redo();
+        assert_eq!(app.history.current(), 2);
+    }
+
+    #[test]
+    fn rematch_keeps_the_player_at_the_bottom() {
+        let mut app = versus(Color::Black, Orientation::White);
+        app.new_game(true);
+        assert_eq!(app.player(), Color::Black);
+        assert_eq!(app.bottom_side(), Color::Black);
+        app.new_game(true);
+        assert_eq!(app.player(), Color::White);
+        assert_eq!(app.bottom_side(), Color::White);
+        // a new game, rather than a rematch, keeps the colours
+        app.new_game(false);
+        assert_eq!(app.bottom_side(), Color::White);
+    }
+
+    #[test]
+    fn analysis_starts_its_engine_when_entered() {
+        let mut app = App::default();
+        app.toggle_analysis();
+        assert!(app.analysis.is_none());
+        assert_eq!(app.status.as_deref(), Some("No engine to analyse with"));
+
+        let events = mpsc::channel().0;
+        app.analyser_setup = Some(AnalyserSetup { engine: None, options: Vec::new(), lines: 1, events });
+        assert!(app.analyser.is_none());
+        app.toggle_analysis();
+        assert!(app.analysis.is_some() && app.analyser.is_some());
+        app.toggle_analysis();
+        assert!(app.analysis.is_none());
+    }
+
+    #[test]
+    fn rematch_keeps_other_orientations() {
+        // watching from the computer's side
+        let mut app = versus(Color::Black, Orientation::Black);
+        app.new_game(true);
+        assert_eq!(app.orientation, Orientation::Black);
+        let mut app = versus(Color::Black, Orientation::SideToMove);
+        app.new_game(true);
+        assert_eq!(app.orientation, Orientation::SideToMove);
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chess::Color;
+
+use crate::clock::TimeControl;
+use crate::game::{Glyphs, Orientation};
+use crate::theme::ColorDepth;
+
+const USAGE: &str = "\
+Usage: tchess [OPTIONS]
+       tchess view <FILE> [--game <N>] [--variations]
+
+Commands:
+  view <FILE>       Replay the games of a PGN file
+
+Options:
+  --fen <FEN>       Start from the position in FEN
+  --tc <CONTROL>    Play with clocks: MINUTES+SECONDS for an increment,
+                    MINUTESdSECONDS for a simple delay or MINUTESbSECONDS
+                    for a Bronstein delay, e.g. 5+3
+  --vs-computer <SIDE>
+                    Play SIDE (white or black) against the computer
+  --level <N>       Computer strength from 1 to 8 [default: 4]
+  --engine <PATH>   Let the UCI engine at PATH play the computer's side
+                    and analyse
+  --engine-option <NAME=VALUE>
+                    Set a UCI option such as Hash, Threads or Skill Level,
+                    may be repeated
+  --lines <N>       Lines shown in analysis mode, from 1 to 5 [default: 3]
+  --orientation <SIDE>
+                    Side at the bottom of the board: white, black, or auto
+                    to follow the side to move [default: your side]
+  --glyphs <SET>    Pieces as nerd (Nerd Font icons), unicode, ascii or
+                    blocks [default: unicode, or ascii without UTF-8]
+  --theme <NAME>    Colour theme: classic, green, blue, high-contrast,
+                    colorblind or one in ~/.config/tchess/themes
+                    [default: classic]
+  --colors <DEPTH>  Colours the terminal can show: truecolor, 256 or 16
+                    [default: guessed from COLORTERM and TERM]
+  --pgn-out <FILE>  Save the game as PGN to FILE when quitting
+  --game <N>        Game of the PGN file to show first [default: 1]
+  --variations      Check and show variations instead of skipping them
+  -h, --help        Print this help";
+
+/// Command line options.
+#[derive(Debug)]
+pub struct Args {
+    /// where to save the game on quit
+    pub pgn_out: Option<PathBuf>,
+    /// starting position
+    pub fen: Option<String>,
+    /// clocks to play with
+    pub time_control: Option<TimeControl>,
+    /// side the human plays against the computer
+    pub vs_computer: Option<Color>,
+    /// computer strength
+    pub level: u8,
+    /// UCI engine to play against and analyse with instead of the built-in one
+    pub engine: Option<PathBuf>,
+    /// UCI options as name and value
+    pub engine_options: Vec<(String, String)>,
+    /// lines shown in analysis mode
+    pub lines: usize,
+    /// side at the bottom of the board, if not the player's
+    pub orientation: Option<Orientation>,
+    /// how pieces are drawn, guessed if not given
+    pub glyphs: Option<Glyphs>,
+    /// colour theme to start with
+    pub theme: Option<String>,
+    /// colour depth, guessed if not given
+    pub colors: Option<ColorDepth>,
+    /// PGN file to replay
+    pub view: Option<PathBuf>,
+    /// 1-based game of the PGN file shown first
+    pub game: usize,
+    /// keep variations when reading PGN
+    pub variations: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            pgn_out: None,
+            fen: None,
+            time_control: None,
+            vs_computer: None,
+            level: 4,
+            engine: None,
+            engine_options: Vec::new(),
+            lines: 3,
+            orientation: None,
+            glyphs: None,
+            theme: None,
+            colors: None,
+            view: None,
+            game: 1,
+            variations: false,
+        }
+    }
+}
+
+impl Args {
+    /// Parses the arguments the program was started with.
+    ///
+    /// Prints the usage and exits on `--help`.
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // accept both `--flag value` and `--flag=value`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value.to_owned())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || match inline.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => bail!("`{flag}` needs a value\n\n{USAGE}"),
+            };
+            match flag.as_str() {
+                "--pgn-out" => parsed.pgn_out = Some(PathBuf::from(value()?)),
+                "--fen" => parsed.fen = Some(value()?),
+                "--tc" => parsed.time_control = Some(value()?.parse()?),
+                "--vs-computer" => {
+                    parsed.vs_computer = Some(match value()?.to_ascii_lowercase().as_str() {
+                        "white" | "w" => Color::White,
+                        "black" | "b" => Color::Black,
+                        side => bail!("`--vs-computer` takes white or black, not `{side}`"),
+                    })
+                }
+                "--orientation" => parsed.orientation = Some(value()?.parse()?),
+                "--glyphs" => parsed.glyphs = Some(value()?.parse()?),
+                "--theme" => parsed.theme = Some(value()?),
+                "--colors" => parsed.colors = Some(value()?.parse()?),
+                "--engine" => parsed.engine = Some(PathBuf::from(value()?)),
+                "--engine-option" => {
+                    let option = value()?;
+                    let Some((name, value)) = option.split_once('=') else {
+                        bail!("`--engine-option` takes NAME=VALUE, not `{option}`");
+                    };
+                    parsed.engine_options.push((name.trim().to_owned(), value.trim().to_owned()));
+                }
+                "--level" => {
+                    let level = value()?;
+                    parsed.level = level
+                        .parse()
+                        .ok()
+                        .filter(|level| (1..=8).contains(level))
+                        .with_context(|| format!("`--level` takes a number from 1 to 8, not `{level}`"))?;
+                }
+                "--lines" => {
+                    let lines = value()?;
+                    parsed.lines = lines
+                        .parse()
+                        .ok()
+                        .filter(|lines| (1..=5).contains(lines))
+                        .with_context(|| format!("`--lines` takes a number from 1 to 5, not `{lines}`"))?;
+                }
+                "--game" => {
+                    let game = value()?;
+                    parsed.game = game.parse().with_context(|| format!("invalid game number `{game}`"))?;
+                }
+                "--variations" => parsed.variations = true,
+                "view" if parsed.view.is_none() => match args.next() {
+                    Some(path) => parsed.view = Some(PathBuf::from(path)),
+                    None => bail!("`view` needs a PGN file\n\n{USAGE}"),
+                },
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => bail!("unknown argument `{arg}`\n\n{USAGE}"),
+            }
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use chess::Color;
+
+/// How a player is compensated for each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bonus {
+    /// Fischer increment, added after every move
+    Increment,
+    /// simple delay, the clock waits this long before counting down
+    Delay,
+    /// Bronstein delay, the time used is given back up to this much
+    Bronstein,
+}
+
+/// Base time per player plus a per-move bonus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Duration,
+    pub kind: Bonus,
+}
+
+impl FromStr for TimeControl {
+    type Err = anyhow::Error;
+
+    /// Reads `MINUTES[+SECONDS]` for an increment, `MINUTES d SECONDS` for a
+    /// simple delay and `MINUTES b SECONDS` for a Bronstein delay.
+    fn from_str(s: &str) -> Result<Self> {
+        let (base, bonus, kind) = match s.find(['+', 'd', 'b']) {
+            Some(i) => {
+                let kind = match &s[i..i + 1] {
+                    "+" => Bonus::Increment,
+                    "d" => Bonus::Delay,
+                    _ => Bonus::Bronstein,
+                };
+                (&s[..i], &s[i + 1..], kind)
+            }
+            None => (s, "0", Bonus::Increment),
+        };
+        let minutes: f64 = base.trim().parse().map_err(|_| anyhow!("invalid base time `{base}` in `{s}`"))?;
+        let seconds: f64 = bonus.trim().parse().map_err(|_| anyhow!("invalid bonus `{bonus}` in `{s}`"))?;
+        let valid = minutes > 0.0 && minutes.is_finite() && seconds >= 0.0 && seconds.is_finite();
+        if !valid {
+            bail!("time control `{s}` needs a positive base time and a non-negative bonus");
+        }
+        Ok(Self {
+            base: Duration::from_secs_f64(minutes * 60.0),
+            bonus: Duration::from_secs_f64(seconds),
+            kind,
+        })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = match self.kind {
+            Bonus::Increment => "+",
+            Bonus::Delay => "d",
+            Bonus::Bronstein => "b",
+        };
+        write!(f, "{}{}{}", self.base.as_secs_f64() / 60.0, separator, self.bonus.as_secs_f64())
+    }
+}
+
+/// A chess clock for both players.
+///
+/// Neither clock runs until [`Clock::start`]; after that the side to move
+/// counts down and [`Clock::press`] hands the turn over.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    pub control: TimeControl,
+    /// time left at the start of each player's current turn, by colour index
+    remaining: [Duration; 2],
+    /// side whose clock is running and when their turn began
+    running: Option<(Color, Instant)>,
+}
+
+impl Clock {
+    /// Constructs a stopped clock with the base time on both sides.
+    pub fn new(control: TimeControl) -> Self {
+        Self { control, remaining: [control.base; 2], running: None }
+    }
+
+    /// Side whose clock is running.
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Starts `color`'s turn, without any bonus for the other side.
+    pub fn start(&mut self, color: Color, now: Instant) {
+        if let Some((running, since)) = self.running {
+            let left = self.left(running, since, now);
+            self.remaining[running.to_index()] = left;
+        }
+        self.running = Some((color, now));
+    }
+
+    /// Stops both clocks, keeping the time left.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((running, since)) = self.running.take() {
+            self.remaining[running.to_index()] = self.left(running, since, now);
+        }
+    }
+
+    /// Ends the running side's turn, applying the bonus and starting the
+    /// opponent's clock.
+    pub fn press(&mut self, now: Instant) {
+        let Some((color, since)) = self.running else {
+            return;
+        };
+        let used = now - since;
+        let mut left = self.left(color, since, now);
+        match self.control.kind {
+            Bonus::Increment => left += self.control.bonus,
+            Bonus::Bronstein => left += used.min(self.control.bonus),
+            Bonus::Delay => {}
+        }
+        self.remaining[color.to_index()] = left;
+        self.running = Some((!color, now));
+    }
+
+    /// Time `color` has left at `now`.
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        match self.running {
+            Some((running, since)) if running == color => self.left(color, since, now),
+            _ => self.remaining[color.to_index()],
+        }
+    }
+
+    /// The side whose time has run out, if any.
+    pub fn flagged(&self, now: Instant) -> Option<Color> {
+        self.running()
+            .filter(|color| self.remaining(*color, now).is_zero())
+    }
+
+    /// Time left for `color`, whose turn started at `since`.
+    fn left(&self, color: Color, since: Instant, now: Instant) -> Duration {
+        let mut used = now.saturating_duration_since(since);
+        if self.control.kind == Bonus::Delay {
+            used = used.saturating_sub(self.control.bonus);
+        }
+        self.remaining[color.to_index()].saturating_sub(used)
+    }
+}
+
+/// Formats a clock reading as `M:SS`, with tenths below ten seconds.
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs < 10 {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    } else if secs < 3600 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn clock(control: &str) -> (Clock, Instant) {
+        let mut clock = Clock::new(control.parse().unwrap());
+        let now = Instant::now();
+        clock.start(Color::White, now);
+        (clock, now)
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        let control: TimeControl = "5+3".parse().unwrap();
+        assert_eq!(control, TimeControl { base: secs(300), bonus: secs(3), kind: Bonus::Increment });
+        assert_eq!("5d3".parse::<TimeControl>().unwrap().kind, Bonus::Delay);
+        assert_eq!("5b3".parse::<TimeControl>().unwrap().kind, Bonus::Bronstein);
+        let control: TimeControl = "0.5".parse().unwrap();
+        assert_eq!((control.base, control.bonus), (secs(30), Duration::ZERO));
+        assert_eq!(control.to_string(), "0.5+0");
+        assert_eq!("15d10".parse::<TimeControl>().unwrap().to_string(), "15d10");
+    }
+
+    #[test]
+    fn rejects_bad_time_controls() {
+        for text in ["", "+3", "5+", "five", "5x3", "0+3", "-5+3", "5+-1", "inf+0", "5+3+1"] {
+            assert!(text.parse::<TimeControl>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn increment_is_added_after_each_move() {
+        let (mut clock, start) = clock("1+2");
+        clock.press(start + secs(5));
+        assert_eq!(clock.remaining(Color::White, start + secs(5)), secs(57));
+        assert_eq!(clock.running(), Some(Color::Black));
+        // only the side to move counts down
+        assert_eq!(clock.remaining(Color::White, start + secs(20)), secs(57));
+        assert_eq!(clock.remaining(Color::Black, start + secs(20)), secs(45));
+    }
+
+    #[test]
+    fn simple_delay_waits_before_counting_down() {
+        let (mut clock, start) = clock("1d3");
+        assert_eq!(clock.remaining(Color::White, start + secs(2)), secs(60));
+        assert_eq!(clock.remaining(Color::White, start + secs(5)), secs(58));
+        clock.press(start + secs(5));
+        // nothing is added on top of the delay
+        assert_eq!(clock.remaining(Color::White, start + secs(5)), secs(58));
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_time_used_up_to_the_delay() {
+        let (mut clock, start) = clock("1b3");
+        clock.press(start + secs(2));
+        assert_eq!(clock.remaining(Color::White, start + secs(2)), secs(60));
+        clock.press(start + secs(12));
+        assert_eq!(clock.remaining(Color::Black, start + secs(12)), secs(53));
+    }
+
+    #[test]
+    fn stopping_keeps_the_time_left() {
+        let (mut clock, start) = clock("1+0");
+        clock.stop(start + secs(10));
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.remaining(Color::White, start + secs(100)), secs(50));
+        // starting the other side charges nobody a bonus
+        clock.start(Color::Black, start + secs(100));
+        assert_eq!(clock.remaining(Color::White, start + secs(110)), secs(50));
+        assert_eq!(clock.remaining(Color::Black, start + secs(110)), secs(50));
+    }
+
+    #[test]
+    fn flags_the_side_out_of_time() {
+        let (mut clock, start) = clock("1+0");
+        assert_eq!(clock.flagged(start + secs(59)), None);
+        assert_eq!(clock.flagged(start + secs(60)), Some(Color::White));
+        assert_eq!(clock.remaining(Color::White, start + secs(90)), Duration::ZERO);
+        // the waiting side never flags
+        clock.press(start + secs(30));
+        assert_eq!(clock.flagged(start + secs(89)), None);
+        assert_eq!(clock.flagged(start + secs(90)), Some(Color::Black));
+        // a delay holds the flag off
+        let (clock, start) = self::clock("1d5");
+        assert_eq!(clock.flagged(start + secs(64)), None);
+        assert_eq!(clock.flagged(start + secs(65)), Some(Color::White));
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(Duration::from_millis(9_450)), "0:09.4");
+        assert_eq!(format_time(secs(75)), "1:15");
+        assert_eq!(format_time(secs(3_725)), "1:02:05");
+    }
+}
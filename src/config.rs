@@ -0,0 +1,110 @@
+//! Where tchess keeps its settings, and the settings file itself.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+
+use crate::clock::TimeControl;
+use crate::game::{Glyphs, Orientation};
+use crate::keymap::Keymap;
+use crate::theme::ColorDepth;
+
+/// `$XDG_CONFIG_HOME/tchess`, or `~/.config/tchess` when that is not set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("tchess"))
+}
+
+/// Settings read from `config.toml` in the configuration directory. Command
+/// line options take precedence over them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// clocks to play with, e.g. `5+3`
+    #[serde(deserialize_with = "parsed")]
+    pub time_control: Option<TimeControl>,
+    /// colour theme, by id or name
+    pub theme: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    pub glyphs: Option<Glyphs>,
+    #[serde(deserialize_with = "parsed")]
+    pub orientation: Option<Orientation>,
+    #[serde(deserialize_with = "parsed")]
+    pub colors: Option<ColorDepth>,
+    /// UCI engine to play against and analyse with
+    pub engine: Option<PathBuf>,
+    /// milliseconds between redraws while nothing happens
+    #[serde(deserialize_with = "positive")]
+    pub tick_rate: Option<u64>,
+    /// bindings of actions to keys
+    pub keys: Keymap,
+}
+
+/// Reads a string setting with its [`FromStr`] implementation.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(|error| serde::de::Error::custom(format!("{error:#}")))
+}
+
+/// Reads a number that must be at least 1.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("must be at least 1")),
+        value => Ok(Some(value)),
+    }
+}
+
+impl Config {
+    /// Reads `config.toml` from the configuration directory, or the defaults
+    /// if there is none.
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).with_context(|| format!("invalid configuration in {}", path.display())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error).with_context(|| format!("cannot read {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_settings() {
+        let config: Config = toml::from_str("time_control = \"5+3\"\ntick_rate = 100\norientation = \"auto\"").unwrap();
+        assert_eq!(config.time_control, Some("5+3".parse().unwrap()));
+        assert_eq!(config.tick_rate, Some(100));
+        assert_eq!(config.orientation, Some(Orientation::SideToMove));
+        assert_eq!(config.keys, Keymap::default());
+    }
+
+    #[test]
+    fn rejects_a_zero_tick_rate_where_it_is_written() {
+        let error = toml::from_str::<Config>("theme = \"green\"\ntick_rate = 0\n").unwrap_err().to_string();
+        assert!(error.contains("line 2, column 13"), "{error}");
+        assert!(error.contains("must be at least 1"), "{error}");
+        assert!(toml::from_str::<Config>("tick_rate = -5").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_values_where_they_are_written() {
+        let error = toml::from_str::<Config>("\n\ntime_control = \"soon\"").unwrap_err().to_string();
+        assert!(error.contains("line 3, column 16"), "{error}");
+        assert!(toml::from_str::<Config>("tick = 10").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Built-in computer opponent.
+
+pub mod eval;
+pub mod search;
+pub mod tt;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::Result;
+use chess::{Board, Color};
+
+use crate::clock::{Bonus, Clock};
+use crate::event::Event;
+use crate::history::GameHistory;
+use crate::uci::{Go, UciEngine};
+pub use search::Limits;
+use search::Searcher;
+use tt::TranspositionTable;
+
+/// Work for the search thread.
+enum Command {
+    Go { id: u64, board: Board, history: Vec<u64>, limits: Limits },
+    Analyse { id: u64, board: Board, history: Vec<u64>, lines: usize },
+}
+
+impl Command {
+    fn id(&self) -> u64 {
+        match self {
+            Command::Go { id, .. } | Command::Analyse { id, .. } => *id,
+        }
+    }
+}
+
+/// Handle to the search thread, which reports its moves as [`Event::Computer`]
+/// and its analysis as [`Event::Analysis`].
+#[derive(Debug)]
+pub struct Engine {
+    /// commands with the flag that abandons them, each search getting its own
+    commands: mpsc::Sender<(Command, Arc<AtomicBool>)>,
+    /// raised to abandon the latest search
+    stop: Arc<AtomicBool>,
+    /// id of the search still wanted, queued searches with another id are skipped
+    wanted: Arc<AtomicU64>,
+    /// id of the search whose move is awaited
+    pending: Option<u64>,
+    next_id: u64,
+}
+
+impl Engine {
+    /// Starts the search thread, which sends its results to `events`.
+    pub fn spawn(events: mpsc::Sender<Event>) -> Self {
+        let (commands, receiver) = mpsc::channel::<(Command, Arc<AtomicBool>)>();
+        let wanted = Arc::new(AtomicU64::new(0));
+        let thread_wanted = wanted.clone();
+        thread::spawn(move || {
+            let mut tt = TranspositionTable::new(18);
+            for (command, stop) in receiver {
+                if command.id() != thread_wanted.load(Ordering::SeqCst) {
+                    continue;
+                }
+                match command {
+                    Command::Go { id, board, history, limits } => {
+                        let mut searcher = Searcher::new(&mut tt, &stop, history);
+                        let best = searcher.search(&board, limits, |_| {});
+                        if let Some(chess_move) = best {
+                            if events.send(Event::Computer(id, chess_move)).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Command::Analyse { id, board, history, lines } => {
+                        let mut searcher = Searcher::new(&mut tt, &stop, history);
+                        let limits = Limits { depth: u8::MAX, movetime: None };
+                        searcher.analyse(&board, limits, lines, |infos| {
+                            let _ = events.send(Event::Analysis(id, infos.to_vec()));
+                        });
+                    }
+                }
+            }
+        });
+        Self { commands, stop: Arc::new(AtomicBool::new(false)), wanted, pending: None, next_id: 0 }
+    }
+
+    /// Starts searching `board`, reached through the positions hashed in `history`.
+    pub fn go(&mut self, board: Board, history: Vec<u64>, limits: Limits) {
+        let id = self.next_id + 1;
+        self.queue(Command::Go { id, board, history, limits });
+    }
+
+    /// Starts analysing the `lines` best moves of `board` until stopped.
+    pub fn analyse(&mut self, board: Board, history: Vec<u64>, lines: usize) {
+        let id = self.next_id + 1;
+        self.queue(Command::Analyse { id, board, history, lines });
+    }
+
+    fn queue(&mut self, command: Command) {
+        self.stop();
+        self.next_id = command.id();
+        self.pending = Some(self.next_id);
+        self.wanted.store(self.next_id, Ordering::SeqCst);
+        // a fresh flag, so stopping the old search can never be undone for the new one
+        self.stop = Arc::new(AtomicBool::new(false));
+        // a closed channel means the thread is gone, so no result will ever arrive
+        if self.commands.send((command, self.stop.clone())).is_err() {
+            self.pending = None;
+        }
+    }
+
+    /// Abandons the search in progress, discarding its move.
+    pub fn stop(&mut self) {
+        if self.pending.take().is_some() {
+            self.wanted.store(0, Ordering::SeqCst);
+            self.stop.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Whether a move is being searched for.
+    pub fn thinking(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Whether search `id` is the one running, so its analysis is still wanted.
+    pub fn current(&self, id: u64) -> bool {
+        self.pending == Some(id)
+    }
+
+    /// Claims the result of search `id`, returning whether it is the one awaited.
+    pub fn accept(&mut self, id: u64) -> bool {
+        if self.pending == Some(id) {
+            self.pending = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What the computer opponent thinks with.
+#[derive(Debug)]
+pub enum Backend {
+    /// the built-in search
+    Native(Engine),
+    /// an external UCI engine
+    Uci(UciEngine),
+}
+
+/// The computer opponent and the side it plays.
+#[derive(Debug)]
+pub struct Computer {
+    pub color: Color,
+    pub limits: Limits,
+    pub backend: Backend,
+}
+
+impl Computer {
+    /// Starts searching the current position of `history`, playing on `clock` if given.
+    pub fn go(&mut self, history: &GameHistory, clock: Option<&Clock>) -> Result<()> {
+        let board = history.position();
+        let now = Instant::now();
+        match &mut self.backend {
+            Backend::Native(engine) => {
+                let mut limits = self.limits;
+                if let Some(clock) = clock {
+                    // spend a fraction of the time left, plus most of the increment
+                    let left = clock.remaining(board.side_to_move(), now);
+                    let budget = left / 30 + clock.control.bonus * 3 / 4;
+                    limits.movetime = Some(limits.movetime.map_or(budget, |time| time.min(budget)));
+                }
+                let positions = (0..history.current()).map(|ply| history.position_at(ply).get_hash()).collect();
+                engine.go(board, positions, limits);
+                Ok(())
+            }
+            Backend::Uci(engine) => {
+                let go = match (clock, self.limits.movetime) {
+                    (Some(clock), _) => {
+                        // UCI knows increments only, delays are left to the engine's margin
+                        let inc = match clock.control.kind {
+                            Bonus::Increment => clock.control.bonus,
+                            Bonus::Delay | Bonus::Bronstein => Default::default(),
+                        };
+                        Go::Clock {
+                            wtime: clock.remaining(Color::White, now),
+                            btime: clock.remaining(Color::Black, now),
+                            winc: inc,
+                            binc: inc,
+                        }
+                    }
+                    (None, Some(time)) => Go::MoveTime(time),
+                    (None, None) => Go::Depth(self.limits.depth.into()),
+                };
+                let moves: Vec<_> = history.plies()[..history.current()].iter().map(|ply| ply.chess_move).collect();
+                engine.go(&history.fen_at(0), &moves, go)
+            }
+        }
+    }
+
+    /// Abandons the search in progress.
+    pub fn stop(&mut self) -> Result<()> {
+        match &mut self.backend {
+            Backend::Native(engine) => {
+                engine.stop();
+                Ok(())
+            }
+            Backend::Uci(engine) => engine.stop(),
+        }
+    }
+
+    /// Whether a move is being searched for.
+    pub fn thinking(&self) -> bool {
+        match &self.backend {
+            Backend::Native(engine) => engine.thinking(),
+            Backend::Uci(engine) => engine.thinking(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn stopped_analysis_does_not_hold_up_the_next_search() {
+        let (events, receiver) = mpsc::channel();
+        let mut engine = Engine::spawn(events);
+        for _ in 0..20 {
+            // stopping right after queueing must end the analysis, however the thread is timed
+            engine.analyse(Board::default(), Vec::new(), 1);
+            engine.stop();
+            engine.go(Board::default(), Vec::new(), Limits { depth: 1, movetime: None });
+            let id = loop {
+                let event = receiver.recv_timeout(Duration::from_secs(10)).expect("the search never ended");
+                if let Event::Computer(id, _) = event {
+                    break id;
+                }
+            };
+            assert!(engine.accept(id));
+            assert!(!engine.thinking());
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chess::{Board, ChessMove, MoveGen, Piece, EMPTY};
+
+use super::eval::{evaluate, PIECE_VALUES};
+use super::tt::{Bound, Entry, TranspositionTable};
+
+/// Score of being checkmated at the root; mates further away score closer to zero.
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = MATE + 1;
+
+/// When to stop searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// deepest iteration to search
+    pub depth: u8,
+    /// time to stop after, whatever depth was reached
+    pub movetime: Option<Duration>,
+}
+
+impl Limits {
+    /// Limits for difficulty `level`, from 1 (weakest) to 8.
+    pub fn level(level: u8) -> Self {
+        let (depth, millis) = match level {
+            0 | 1 => (1, None),
+            2 => (2, None),
+            3 => (3, None),
+            4 => (4, Some(1_000)),
+            5 => (6, Some(2_000)),
+            6 => (8, Some(3_000)),
+            7 => (64, Some(5_000)),
+            _ => (64, Some(10_000)),
+        };
+        Self { depth, movetime: millis.map(Duration::from_millis) }
+    }
+}
+
+/// Result of one completed iteration.
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: u8,
+    /// centipawns from the side to move's point of view
+    pub score: i32,
+    pub nodes: u64,
+    /// principal variation, best move first
+    pub pv: Vec<ChessMove>,
+}
+
+/// Alpha-beta searcher with iterative deepening.
+pub struct Searcher<'a> {
+    tt: &'a mut TranspositionTable,
+    stop: &'a AtomicBool,
+    deadline: Option<Instant>,
+    nodes: u64,
+    aborted: bool,
+    /// hashes of the positions leading here, for repetition checks
+    path: Vec<u64>,
+}
+
+impl<'a> Searcher<'a> {
+    /// Constructs a searcher; `history` holds the hashes of earlier game positions.
+    pub fn new(tt: &'a mut TranspositionTable, stop: &'a AtomicBool, history: Vec<u64>) -> Self {
+        Self { tt, stop, deadline: None, nodes: 0, aborted: false, path: history }
+    }
+
+    /// Searches `board` with growing depth until `limits` or the stop flag end it,
+    /// calling `on_iteration` after every completed depth.
+    ///
+    /// Returns the best move found, or `None` when there is no legal move.
+    pub fn search(
+        &mut self,
+        board: &Board,
+        limits: Limits,
+        mut on_iteration: impl FnMut(&SearchInfo),
+    ) -> Option<ChessMove> {
+        let mut best = MoveGen::new_legal(board).next()?;
+        self.deadline = limits.movetime.map(|time| Instant::now() + time);
+
+        for depth in 1..=limits.depth.max(1) {
+            let score = self.negamax(board, depth, -INFINITY, INFINITY, 0);
+            if self.aborted {
+                break;
+            }
+            let pv = self.principal_variation(board, depth);
+            if let Some(first) = pv.first() {
+                best = *first;
+            }
+            on_iteration(&SearchInfo { depth, score, nodes: self.nodes, pv });
+            if score.abs() >= MATE - i32::from(depth) {
+                break;
+            }
+        }
+        Some(best)
+    }
+
+    /// Searches `board` for its `lines` best moves with growing depth until `limits`
+    /// or the stop flag end it, calling `on_iteration` with the lines, best first,
+    /// after every completed depth.
+    pub fn analyse(&mut self, board: &Board, limits: Limits, lines: usize, mut on_iteration: impl FnMut(&[SearchInfo])) {
+        self.deadline = limits.movetime.map(|time| Instant::now() + time);
+        for depth in 1..=limits.depth.max(1) {
+            let mut infos: Vec<SearchInfo> = Vec::new();
+            // each line is the best move left once the better lines are excluded
+            while infos.len() < lines {
+                let excluded: Vec<ChessMove> = infos.iter().map(|info| info.pv[0]).collect();
+                let Some((score, chess_move)) = self.root(board, depth, &excluded) else {
+                    break;
+                };
+                let mut pv = vec![chess_move];
+                pv.extend(self.principal_variation(&board.make_move_new(chess_move), depth - 1));
+                infos.push(SearchInfo { depth, score, nodes: self.nodes, pv });
+            }
+            if self.aborted || infos.is_empty() {
+                break;
+            }
+            on_iteration(&infos);
+        }
+    }
+
+    /// Best score and move at `board` among the moves not `excluded`.
+    fn root(&mut self, board: &Board, depth: u8, excluded: &[ChessMove]) -> Option<(i32, ChessMove)> {
+        let hash = board.get_hash();
+        let moves = ordered_moves(board, MoveGen::new_legal(board), self.tt.probe(hash).and_then(|e| e.best));
+        let mut best: Option<(i32, ChessMove)> = None;
+        self.path.push(hash);
+        for chess_move in moves.into_iter().filter(|m| !excluded.contains(m)) {
+            let alpha = best.map_or(-INFINITY, |(score, _)| score);
+            let score = -self.negamax(&board.make_move_new(chess_move), depth - 1, -INFINITY, -alpha, 1);
+            if self.aborted {
+                break;
+            }
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, chess_move));
+            }
+        }
+        self.path.pop();
+        best
+    }
+
+    fn negamax(&mut self, board: &Board, depth: u8, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        let hash = board.get_hash();
+        if ply > 0 && self.path.contains(&hash) {
+            return 0;
+        }
+
+        let entry = self.tt.probe(hash);
+        if let Some(entry) = entry.filter(|e| ply > 0 && e.depth >= depth) {
+            let score = from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        let in_check = *board.checkers() != EMPTY;
+        let moves = ordered_moves(board, MoveGen::new_legal(board), entry.and_then(|e| e.best));
+        if moves.is_empty() {
+            return if in_check { -(MATE - ply) } else { 0 };
+        }
+        if depth == 0 {
+            return self.quiesce(board, alpha, beta);
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = moves[0];
+        self.path.push(hash);
+        for chess_move in moves {
+            let child = board.make_move_new(chess_move);
+            let score = -self.negamax(&child, depth - 1, -beta, -alpha, ply + 1);
+            if self.aborted {
+                break;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = chess_move;
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        self.path.pop();
+
+        if !self.aborted {
+            let bound = if best_score <= original_alpha {
+                Bound::Upper
+            } else if best_score >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.tt.store(Entry { hash, depth, score: to_tt(best_score, ply), bound, best: Some(best_move) });
+        }
+        best_score
+    }
+
+    /// Searches captures only, so the evaluation is not taken mid-exchange.
+    fn quiesce(&mut self, board: &Board, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        let stand_pat = evaluate(board);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut captures = MoveGen::new_legal(board);
+        captures.set_iterator_mask(*board.color_combined(!board.side_to_move()));
+        for chess_move in ordered_moves(board, captures, None) {
+            let score = -self.quiesce(&board.make_move_new(chess_move), -beta, -alpha);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    /// Follows the best moves stored in the table from `board`.
+    fn principal_variation(&self, board: &Board, depth: u8) -> Vec<ChessMove> {
+        let mut pv = Vec::new();
+        let mut board = *board;
+        let mut seen = Vec::new();
+        while let Some(chess_move) = self.tt.probe(board.get_hash()).and_then(|e| e.best) {
+            if pv.len() >= usize::from(depth) || seen.contains(&board.get_hash()) || !board.legal(chess_move) {
+                break;
+            }
+            seen.push(board.get_hash());
+            pv.push(chess_move);
+            board = board.make_move_new(chess_move);
+        }
+        pv
+    }
+
+    fn should_stop(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.aborted && self.nodes.is_multiple_of(1024) {
+            let late = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            self.aborted = late || self.stop.load(Ordering::Relaxed);
+        }
+        self.aborted
+    }
+}
+
+/// Moves of `moves` with the table's best move first, then captures of the most
+/// valuable piece by the least valuable one, then promotions.
+fn ordered_moves(board: &Board, moves: MoveGen, best: Option<ChessMove>) -> Vec<ChessMove> {
+    let mut moves: Vec<(i32, ChessMove)> = moves
+        .map(|m| {
+            let score = if Some(m) == best {
+                1_000_000
+            } else if let Some(victim) = board.piece_on(m.get_dest()) {
+                let attacker = board.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
+                10_000 + PIECE_VALUES[victim.to_index()] * 10 - PIECE_VALUES[attacker.to_index()] / 10
+            } else if let Some(promotion) = m.get_promotion() {
+                5_000 + PIECE_VALUES[promotion.to_index()]
+            } else {
+                0
+            };
+            (score, m)
+        })
+        .collect();
+    moves.sort_by_key(|(score, _)| -score);
+    moves.into_iter().map(|(_, m)| m).collect()
+}
+
+/// Mate scores are stored relative to the position rather than the root.
+fn to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - 1000 {
+        score + ply
+    } else if score <= -(MATE - 1000) {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - 1000 {
+        score - ply
+    } else if score <= -(MATE - 1000) {
+        score + ply
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use chess::Square;
+
+    fn best(fen: &str, depth: u8) -> ChessMove {
+        let board = Board::from_str(fen).unwrap();
+        let mut tt = TranspositionTable::new(12);
+        let stop = AtomicBool::new(false);
+        let mut searcher = Searcher::new(&mut tt, &stop, Vec::new());
+        searcher.search(&board, Limits { depth, movetime: None }, |_| {}).unwrap()
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        for depth in 1..=3 {
+            assert_eq!(best("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", depth), ChessMove::from_str("a1a8").unwrap());
+            assert_eq!(best("r3k3/8/8/8/8/8/5PPP/6K1 b - - 0 1", depth), ChessMove::from_str("a8a1").unwrap());
+        }
+    }
+
+    #[test]
+    fn does_not_hang_a_piece() {
+        // the knight is attacked by a pawn and has to move somewhere safe
+        let fen = "4k3/8/8/2p5/3N4/8/8/4K3 w - - 0 1";
+        let chess_move = best(fen, 2);
+        assert_eq!(chess_move.get_source(), Square::D4);
+        let after = Board::from_str(fen).unwrap().make_move_new(chess_move);
+        let knight = chess_move.get_dest();
+        assert!(MoveGen::new_legal(&after).all(|m| m.get_dest() != knight), "{chess_move} leaves the knight en prise");
+
+        // the pawn on d5 is defended, so taking it loses the queen
+        let fen = "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1";
+        assert_ne!(best(fen, 2), ChessMove::from_str("d1d5").unwrap());
+    }
+}
@@ -0,0 +1,52 @@
+use chess::ChessMove;
+
+/// How a stored score relates to the true value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// the search failed high, the score is at least this
+    Lower,
+    /// the search failed low, the score is at most this
+    Upper,
+}
+
+/// A searched position.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u8,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<ChessMove>,
+}
+
+/// Transposition table keyed by `Board::get_hash`, always replacing on collision.
+#[derive(Debug)]
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    /// Constructs a table with `2^bits` entries.
+    pub fn new(bits: u32) -> Self {
+        Self { entries: vec![None; 1 << bits] }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.entries.len() - 1)
+    }
+
+    /// The entry stored for `hash`, if any.
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        self.entries[self.index(hash)].filter(|entry| entry.hash == hash)
+    }
+
+    /// Stores `entry`, keeping a deeper entry for the same position.
+    pub fn store(&mut self, entry: Entry) {
+        let index = self.index(entry.hash);
+        match self.entries[index] {
+            Some(old) if old.hash == entry.hash && old.depth > entry.depth => {}
+            _ => self.entries[index] = Some(entry),
+        }
+    }
+}
@@ -5,11 +5,15 @@ use std::{
 };
 
 use anyhow::Result;
+use chess::ChessMove;
 use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
 
+use crate::engine::search::SearchInfo;
+use crate::uci::UciMessage;
+
 
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
   /// Terminal tick.
   Tick,
@@ -19,6 +23,16 @@ pub enum Event {
   Mouse(MouseEvent),
   /// Terminal resize.
   Resize(u16, u16),
+  /// Bracketed paste.
+  Paste(String),
+  /// Move found by the computer opponent, tagged with its search id.
+  Computer(u64, ChessMove),
+  /// Output of the UCI engine playing the computer's side.
+  Uci(UciMessage),
+  /// Lines found by the built-in engine in analysis mode, tagged with its search id.
+  Analysis(u64, Vec<SearchInfo>),
+  /// Output of the UCI engine analysing.
+  UciAnalysis(UciMessage),
 }
 
 /// Terminal event handler.
@@ -28,8 +42,6 @@ pub struct EventHandler {
   sender: mpsc::Sender<Event>,
   /// Event receiver channel.
   receiver: mpsc::Receiver<Event>,
-  /// Event handler thread.
-  handler: thread::JoinHandle<()>,
 }
 
 impl EventHandler {
@@ -37,7 +49,7 @@ impl EventHandler {
   pub fn new(tick_rate: u64) -> Self {
     let tick_rate = Duration::from_millis(tick_rate);
     let (sender, receiver) = mpsc::channel();
-    let handler = {
+    {
       let sender = sender.clone();
       thread::spawn(move || {
         let mut last_tick = Instant::now();
@@ -55,7 +67,8 @@ impl EventHandler {
               },
               CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
               CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
-              _ => unimplemented!(),
+              CrosstermEvent::Paste(text) => sender.send(Event::Paste(text)),
+              CrosstermEvent::FocusGained | CrosstermEvent::FocusLost => Ok(()),
             }
             .expect("failed to send terminal event")
           }
@@ -65,9 +78,14 @@ impl EventHandler {
             last_tick = Instant::now();
           }
         }
-      })
-    };
-    Self { sender, receiver, handler }
+      });
+    }
+    Self { sender, receiver }
+  }
+
+  /// Returns a sender for events produced outside the terminal, such as engine moves.
+  pub fn sender(&self) -> mpsc::Sender<Event> {
+    self.sender.clone()
   }
 
   /// Receive the next event from the handler thread.
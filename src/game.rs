@@ -1,23 +1,118 @@
 use std::fmt::Debug;
 use std::str::FromStr;
 
-use chess::{Game, Square, Piece, ChessMove, Rank};
-use ratatui::style::Color;
+use chess::{Action, BitBoard, Board, Game, MoveGen, Square, Piece, Rank, File, EMPTY};
+use ratatui::style::{Modifier, Style};
 use ratatui::widgets::StatefulWidget;
-use ratatui::style::{Styled, Style};
 use ratatui::prelude::{Rect, Buffer};
 
-struct MyPiece(Piece);
+use crate::theme::Palette;
+
+/// How pieces are drawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    /// icons of a patched Nerd Font
+    NerdFont,
+    /// the chess symbols of Unicode
+    #[default]
+    Unicode,
+    /// letters, upper case for White and lower case for Black
+    Ascii,
+    /// pictures drawn with block characters, on squares large enough
+    Blocks,
+}
+
+impl Glyphs {
+    /// Guesses what the terminal can show: letters where it is unlikely to
+    /// have chess symbols, Unicode otherwise. Nerd Font icons cannot be
+    /// detected, so they are never guessed.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let utf8 = locale.contains("UTF-8") || locale.contains("UTF8");
+        // the Linux console font has no chess symbols
+        let console = std::env::var("TERM").is_ok_and(|term| term == "linux");
+        if utf8 && !console {
+            Glyphs::Unicode
+        } else {
+            Glyphs::Ascii
+        }
+    }
+
+    /// Mark on the squares the selected piece can move to.
+    fn dot(self) -> char {
+        match self {
+            Glyphs::NerdFont => '⬤',
+            Glyphs::Unicode | Glyphs::Blocks => '●',
+            Glyphs::Ascii => '*',
+        }
+    }
+
+    /// Character for `piece` of `color`, as drawn on squares too small for pictures.
+    pub fn piece(self, piece: Piece, color: chess::Color) -> char {
+        MyPiece(piece, color).char(self)
+    }
+
+    /// Block picture of `piece`, three cells wide and two tall, if pieces are
+    /// drawn as pictures.
+    pub fn picture(self, piece: Piece, color: chess::Color) -> Option<[&'static str; 2]> {
+        (self == Glyphs::Blocks).then(|| MyPiece(piece, color).art())
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "nerd" | "nerdfont" | "nerd-font" => Ok(Glyphs::NerdFont),
+            "unicode" => Ok(Glyphs::Unicode),
+            "ascii" => Ok(Glyphs::Ascii),
+            "blocks" => Ok(Glyphs::Blocks),
+            _ => anyhow::bail!("glyph set is nerd, unicode, ascii or blocks, not `{s}`"),
+        }
+    }
+}
+
+struct MyPiece(Piece, chess::Color);
 
 impl MyPiece {
-    fn char(&self) -> char {
+    fn char(&self, glyphs: Glyphs) -> char {
+        match glyphs {
+            Glyphs::NerdFont => match self.0 {
+                Piece::Pawn   => '󰡙',
+                Piece::King   => '󰡗',
+                Piece::Queen  => '󰡚',
+                Piece::Knight => '󰡘',
+                Piece::Bishop => '󰡜',
+                Piece::Rook   => '󰡛',
+            },
+            // the filled symbols, coloured like the other sets
+            Glyphs::Unicode | Glyphs::Blocks => match self.0 {
+                Piece::Pawn   => '♟',
+                Piece::King   => '♚',
+                Piece::Queen  => '♛',
+                Piece::Knight => '♞',
+                Piece::Bishop => '♝',
+                Piece::Rook   => '♜',
+            },
+            // FEN letters, `K` for a white king and `k` for a black one
+            Glyphs::Ascii => self.0.to_string(self.1).chars().next().unwrap_or('?'),
+        }
+    }
+
+    /// Rows of the block picture, three cells wide.
+    fn art(&self) -> [&'static str; 2] {
         match self.0 {
-            Piece::Pawn   => '󰡙',
-            Piece::King   => '󰡗',
-            Piece::Queen  => '󰡚',
-            Piece::Knight => '󰡘',
-            Piece::Bishop => '󰡜',
-            Piece::Rook   => '󰡛',
+            Piece::Pawn   => [" ▄ ", "▗█▖"],
+            Piece::King   => [" ✚ ", "▐█▌"],
+            Piece::Queen  => ["▚▲▞", "▐█▌"],
+            Piece::Knight => ["▄▛▀", " █▌"],
+            Piece::Bishop => [" ▲ ", "▐█▌"],
+            Piece::Rook   => ["▙▄▟", "▐█▌"],
         }
     }
 }
@@ -31,169 +126,255 @@ impl Default for MyGame {
     }
 }
 
+/// Which side is drawn at the bottom of the board.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    White,
+    Black,
+    /// the side to move, for hotseat games
+    SideToMove,
+}
+
+impl FromStr for Orientation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "white" | "w" => Ok(Orientation::White),
+            "black" | "b" => Ok(Orientation::Black),
+            "auto" => Ok(Orientation::SideToMove),
+            _ => anyhow::bail!("orientation is white, black or auto, not `{s}`"),
+        }
+    }
+}
+
+/// A piece being dragged with the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drag {
+    /// board row and column the piece is dragged from
+    pub from: (u16, u16),
+    /// terminal cell under the pointer
+    pub at: (u16, u16),
+    /// whether the pointer has left the piece's square, making this a drag
+    /// rather than a click
+    pub moved: bool,
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct MyGameState {
-    pub selected: Option<(u16, u16)>
+    /// board row and column of the selected piece
+    selected: Option<(u16, u16)>,
+    /// squares the selected piece can legally move to
+    destinations: BitBoard,
+    /// keyboard cursor, hidden until the first navigation key
+    pub cursor: Option<(u16, u16)>,
+    /// whether Black is drawn at the bottom
+    pub flipped: bool,
+    /// where the board was last drawn, empty if it did not fit
+    pub board: Rect,
+    /// cells of each square as last drawn, by board row and column
+    pub squares: [[Rect; 8]; 8],
+    /// how pieces are drawn
+    pub glyphs: Glyphs,
+    /// colours of the board and pieces
+    pub palette: Palette,
+    /// piece being dragged, if any
+    pub drag: Option<Drag>,
 }
 
 impl MyGameState {
-    pub fn to_chess_notation(&self) -> Option<String> {
-        match self.selected {
-            None => None,
-            Some((rank, file)) => {
-                Some(format!("{}{}",char::from_u32(file as u32 + 97).unwrap(),8-rank))
-            } 
+    pub fn selected(&self) -> Option<(u16, u16)> {
+        self.selected
+    }
+
+    /// Selects the piece at board `(row, col)`, working out where it can
+    /// move in `board` once rather than on every frame.
+    pub fn select(&mut self, (row, col): (u16, u16), board: &Board) {
+        let from = to_square(row, col);
+        self.selected = Some((row, col));
+        self.destinations = MoveGen::new_legal(board)
+            .filter(|chess_move| chess_move.get_source() == from)
+            .fold(EMPTY, |destinations, chess_move| destinations | BitBoard::from_square(chess_move.get_dest()));
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected = None;
+        self.destinations = EMPTY;
+    }
+
+    /// Whether the selected piece can legally move to board `(row, col)`.
+    pub fn can_move_to(&self, (row, col): (u16, u16)) -> bool {
+        self.destinations & BitBoard::from_square(to_square(row, col)) != EMPTY
+    }
+
+    /// Screen row and column of the square at board `(row, col)`, or the
+    /// reverse, as flipping the board mirrors both axes.
+    pub fn on_screen(&self, (row, col): (u16, u16)) -> (u16, u16) {
+        if self.flipped {
+            (7 - row, 7 - col)
+        } else {
+            (row, col)
         }
     }
+
+    /// Board row and column of the square drawn at terminal cell `(x, y)`.
+    pub fn square_at(&self, x: u16, y: u16) -> Option<(u16, u16)> {
+        (0..8u16)
+            .flat_map(|row| (0..8u16).map(move |col| (row, col)))
+            .find(|&(row, col)| {
+                let area = self.squares[row as usize][col as usize];
+                (area.left()..area.right()).contains(&x) && (area.top()..area.bottom()).contains(&y)
+            })
+    }
 }
 
+/// Square sizes in terminal cells, largest first. Cells are about twice as
+/// tall as they are wide, so squares are roughly twice as wide as tall.
+const SQUARE_SIZES: [(u16, u16); 5] = [(9, 5), (7, 3), (5, 3), (3, 1), (2, 1)];
+
+/// Smallest area the board can be drawn in.
+pub const MIN_BOARD: (u16, u16) = (8 * SQUARE_SIZES[4].0, 8 * SQUARE_SIZES[4].1);
+
 impl StatefulWidget for MyGame {
     type State = MyGameState;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
-        if area.width < 40 || area.height < 24 {
+        let Some((width, height)) = SQUARE_SIZES
+            .into_iter()
+            .find(|&(width, height)| 8 * width <= area.width && 8 * height <= area.height)
+        else {
+            state.board = Rect::default();
+            state.squares = Default::default();
             return;
-        }
+        };
 
-        let offset_x = ((area.width - 40) / 2) + area.x;
-        let offset_y = ((area.height - 24) / 2) + area.y;
+        let offset_x = ((area.width - 8 * width) / 2) + area.x;
+        let offset_y = ((area.height - 8 * height) / 2) + area.y;
+        state.board = Rect::new(offset_x, offset_y, 8 * width, 8 * height);
+        for row in 0..8 {
+            for col in 0..8 {
+                let (y, x) = state.on_screen((row, col));
+                state.squares[row as usize][col as usize] =
+                    Rect::new(offset_x + width * x, offset_y + height * y, width, height);
+            }
+        }
+        // the cell a square's piece or dot is drawn in
+        let centre = |(row, col): (u16, u16)| {
+            let square = state.squares[row as usize][col as usize];
+            (square.x + square.width / 2, square.y + square.height / 2)
+        };
 
-        for i in 0..8 {
-            for j in 0..8 {
-                let color = if (i + j) % 2 == 0 {
-                    Color::Rgb(0xc0, 0xc1, 0xc3)
+        for (row, squares) in state.squares.iter().enumerate() {
+            for (col, &square) in squares.iter().enumerate() {
+                let color = if (row + col) % 2 == 0 {
+                    state.palette.light_square
                 } else {
-                    Color::Rgb(0xb5,0x87,0x63)
+                    state.palette.dark_square
                 };
-                for k in 0..5 {
-                   for l in 0..3 {
-                       buf.get_mut(offset_x+5*i+k, offset_y+3*j+l).set_bg(color);
-                   }
-                }
+                buf.set_style(square, Style::new().bg(color));
             }
         }
 
-        for (rank, y) in (1..=8).rev().zip(0u16..) {
-            for (file, x) in ('a'..='h').zip(0u16..) {
-                let pos = self.0.current_position();
-                let square = Square::from_str(&format!("{file}{rank}")[..]).unwrap();
+        // what changed with the last move, and the king it may have checked,
+        // under the pieces so the selection and dots still go over them
+        let tint = |buf: &mut Buffer, square: Square, color| {
+            let area = state.squares[7 - square.get_rank().to_index()][square.get_file().to_index()];
+            buf.set_style(area, Style::new().bg(color));
+        };
+        let last_move = self.0.actions().iter().rev().find_map(|action| match action {
+            Action::MakeMove(chess_move) => Some(*chess_move),
+            _ => None,
+        });
+        if let Some(last_move) = last_move {
+            tint(buf, last_move.get_source(), state.palette.last_move);
+            tint(buf, last_move.get_dest(), state.palette.last_move);
+        }
+        let pos = self.0.current_position();
+        if pos.checkers().popcnt() > 0 {
+            tint(buf, pos.king_square(pos.side_to_move()), state.palette.check);
+        }
+
+        // a dragged piece is drawn under the pointer instead of on its square
+        let dragged = state.drag.filter(|drag| drag.moved).map(|drag| drag.from);
+        for row in 0..8u16 {
+            for col in 0..8u16 {
+                let square = to_square(row, col);
+                if dragged == Some((row, col)) {
+                    continue;
+                }
                 if let Some(piece) = pos.piece_on(square) {
-                    let fg_color = if let chess::Color::White = pos.color_on(square).unwrap() {
-                        Color::White
+                    let color = pos.color_on(square).unwrap();
+                    let fg_color = if let chess::Color::White = color {
+                        state.palette.white_piece
                     } else {
-                        Color::Black
+                        state.palette.black_piece
                     };
-                    buf.get_mut(offset_x+(5*x)+2, offset_y+(3*y)+1).set_fg(fg_color).set_char(MyPiece(piece).char());
+                    let piece = MyPiece(piece, color);
+                    let (x, y) = centre((row, col));
+                    let area = state.squares[row as usize][col as usize];
+                    if state.glyphs == Glyphs::Blocks && area.width >= 5 && area.height >= 3 {
+                        // the bottom row of the picture sits on the centre row
+                        for (line, y) in piece.art().into_iter().zip(y - 1..) {
+                            for (c, x) in line.chars().zip(x - 1..) {
+                                buf.get_mut(x, y).set_fg(fg_color).set_char(c);
+                            }
+                        }
+                    } else {
+                        buf.get_mut(x, y).set_fg(fg_color).set_char(piece.char(state.glyphs));
+                    }
                 }
             }
         }
 
-        if let Some((y, x)) = state.selected {
-            for i in 0..5 {
-                for j in 0..3 {
-                    buf.get_mut(offset_x+(5*x)+i, offset_y+(3*y)+j).set_bg(Color::Rgb(0x33, 0xa0, 0x33));
+        if let Some((row, col)) = state.selected {
+            let square = state.squares[row as usize][col as usize];
+            buf.set_style(square, Style::new().bg(state.palette.selected));
+        }
+
+        if let Some((row, col)) = state.cursor {
+            let square = state.squares[row as usize][col as usize];
+            let (_, y) = centre((row, col));
+            if square.width >= 5 {
+                for (x, c) in [(square.left(), '['), (square.right() - 1, ']')] {
+                    buf.get_mut(x, y)
+                        .set_char(c)
+                        .set_fg(state.palette.legal_move);
                 }
+            } else {
+                // no room for brackets beside the piece
+                buf.set_style(Rect::new(square.x, y, square.width, 1), Style::new().add_modifier(Modifier::UNDERLINED));
             }
         }
 
-        if let Some(_) = state.selected {
-            for i in 0u8..8 {
-                for j in 0u8..8 {
-                    let int = 8 * (7 - i) + j;
-                    let end_square: Square;
-                    unsafe {
-                        end_square = Square::new(int);
-                    }
-                    let start_square = Square::from_str(&state.to_chess_notation().unwrap()[..]).unwrap();
-                    let chess_move = ChessMove::new(start_square, end_square, None);
-                    if self.0.current_position().legal(chess_move) {
-                        let buf_ref = buf.get_mut(offset_x+(5*j as u16)+2, offset_y+(3*i as u16)+1);
-                        if buf_ref.symbol == " ".to_owned() {
-                            buf_ref.set_char('⬤').set_fg(Color::Rgb(0x33, 0xa0, 0x33));
-                        } else {
-                            buf_ref.set_fg(Color::Rgb(0x33, 0xa0, 0x33));
-                        }
-                                        
-                    }
-                }
+        for square in state.destinations {
+            let (x, y) = centre((7 - square.get_rank().to_index() as u16, square.get_file().to_index() as u16));
+            let cell = buf.get_mut(x, y);
+            if cell.symbol == " " {
+                cell.set_char(state.glyphs.dot()).set_fg(state.palette.legal_move);
+            } else {
+                cell.set_fg(state.palette.legal_move);
             }
-            let selected_square = Square::from_str(&state.to_chess_notation().unwrap()[..]).unwrap();
-            if Some(Piece::Pawn) == self.0.current_position().piece_on(selected_square) {
-                let color = self.0.current_position().color_on(selected_square);
-                if let Some(color) = color {
-                    if color == chess::Color::Black && selected_square.get_rank() == Rank::Second {
-                        let square_1 = selected_square.down().unwrap();
-                        let square_2 = selected_square.down().unwrap().uright();
-                        let square_3 = selected_square.down().unwrap().uleft();
-                        let square_vec = vec![square_1, square_2, square_3];
-
-                        for (i, square) in square_vec.iter().enumerate() {
-                            if self.0.current_position().legal(ChessMove::new(selected_square, *square, Some(Piece::Queen))) {
-                                let offset_x = offset_x + (state.selected.unwrap().1 * 5);
-                                let offset_y = offset_y + (state.selected.unwrap().0 * 3) + 3;
-                                match i {
-                                    0 => {
-                                        buf.get_mut(offset_x + 1, offset_y).set_char('󰡚').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 3, offset_y).set_char('󰡛').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 1, offset_y + 2).set_char('󰡘').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 3, offset_y + 2).set_char('󰡜').set_fg(Color::Black);
-                                    },
-                                    1 => {
-                                        buf.get_mut(offset_x + 6, offset_y).set_char('󰡚').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 8, offset_y).set_char('󰡛').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 6, offset_y + 2).set_char('󰡘').set_fg(Color::Black);
-                                        buf.get_mut(offset_x + 8, offset_y + 2).set_char('󰡜').set_fg(Color::Black);
-                                    },
-                                    2 => {
-                                        buf.get_mut(offset_x -4, offset_y).set_char('󰡚').set_fg(Color::Black);
-                                        buf.get_mut(offset_x - 2, offset_y).set_char('󰡛').set_fg(Color::Black);
-                                        buf.get_mut(offset_x - 4, offset_y + 2).set_char('󰡘').set_fg(Color::Black);
-                                        buf.get_mut(offset_x - 2, offset_y + 2).set_char('󰡜').set_fg(Color::Black);
-                                    },
-                                    _ => (),
-                                }
-                            }
-                        }
-                    } else if color == chess::Color::White && selected_square.get_rank() == Rank::Seventh {
-                        let square_1 = selected_square.up().unwrap();
-                        let square_2 = selected_square.up().unwrap().uright();
-                        let square_3 = selected_square.up().unwrap().uleft();
-                        let square_vec = vec![square_1, square_2, square_3];
-
-                        for (i, square) in square_vec.iter().enumerate() {
-                            if self.0.current_position().legal(ChessMove::new(selected_square, *square, Some(Piece::Queen))) {
-                                let offset_x = offset_x + (state.selected.unwrap().1 * 5);
-                                let offset_y = offset_y + (state.selected.unwrap().0 * 3) - 3;
-                                match i {
-                                    0 => {
-                                        buf.get_mut(offset_x + 1, offset_y).set_char('󰡚').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 3, offset_y).set_char('󰡛').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 1, offset_y + 2).set_char('󰡘').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 3, offset_y + 2).set_char('󰡜').set_fg(Color::White);
-                                    },
-                                    1 => {
-                                        buf.get_mut(offset_x + 6, offset_y).set_char('󰡚').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 8, offset_y).set_char('󰡛').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 6, offset_y + 2).set_char('󰡘').set_fg(Color::White);
-                                        buf.get_mut(offset_x + 8, offset_y + 2).set_char('󰡜').set_fg(Color::White);
-                                    },
-                                    2 => {
-                                        buf.get_mut(offset_x -4, offset_y).set_char('󰡚').set_fg(Color::White);
-                                        buf.get_mut(offset_x - 2, offset_y).set_char('󰡛').set_fg(Color::White);
-                                        buf.get_mut(offset_x - 4, offset_y + 2).set_char('󰡘').set_fg(Color::White);
-                                        buf.get_mut(offset_x - 2, offset_y + 2).set_char('󰡜').set_fg(Color::White);
-                                    },
-                                    _ => (),
-                                }
-                            }
-                        }
-                    }
-                }
+        }
+
+        // the ghost of a dragged piece goes over everything else
+        if let Some(drag) = state.drag.filter(|drag| drag.moved) {
+            let pos = self.0.current_position();
+            let square = to_square(drag.from.0, drag.from.1);
+            let (x, y) = drag.at;
+            let on_screen = (buf.area.left()..buf.area.right()).contains(&x) && (buf.area.top()..buf.area.bottom()).contains(&y);
+            if let (Some(piece), Some(color), true) = (pos.piece_on(square), pos.color_on(square), on_screen) {
+                let fg_color = if color == chess::Color::White { state.palette.white_piece } else { state.palette.black_piece };
+                buf.get_mut(x, y)
+                    .set_char(MyPiece(piece, color).char(state.glyphs))
+                    .set_fg(fg_color)
+                    .set_style(Style::new().add_modifier(Modifier::BOLD));
             }
         }
     }
 }
 
-pub fn to_chess_notation(rank: u16, file: u16) -> String {
-    format!("{}{}",char::from_u32(file as u32 + 97).unwrap(),8-rank)
+/// Converts a board row (0 is the eighth rank) and column (0 is the a-file) to a [`Square`].
+pub fn to_square(rank: u16, file: u16) -> Square {
+    Square::make_square(Rank::from_index(7 - rank as usize), File::from_index(file as usize))
 }
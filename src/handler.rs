@@ -1,133 +1,318 @@
-use std::str::FromStr;
-
-use crate::app::App;
-use crate::game::to_chess_notation;
-use chess::{Square, ChessMove, Piece, Rank};
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind, MouseButton};
-use crossterm::terminal::size;
+use crate::app::{App, GameAction, PROMOTION_PIECES};
+use crate::game::{to_square, Drag};
+use crate::keymap::Action;
+use chess::{ChessMove, Piece};
+use crossterm::event::{KeyEvent, MouseEvent, MouseEventKind, MouseButton};
+use ratatui::layout::Rect;
 use anyhow::{Result, Ok};
 
+/// Runs the first action bound to `key_event` that the current screen knows.
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> Result<()> {
-    match key_event.code {
-        // Exit application on `ESC` or `q`
-        KeyCode::Esc | KeyCode::Char('q') => {
-            app.quit();
+    let handle = if app.promotion.is_some() {
+        handle_promotion_action
+    } else if app.viewer.is_some() && app.analysis.is_none() {
+        handle_viewer_action
+    } else if app.outcome.is_some() && app.analysis.is_none() {
+        handle_game_over_action
+    } else {
+        handle_play_action
+    };
+    let mut actions = app.keymap.actions(&key_event);
+    if app.promotion.is_some() {
+        // `q` picks a queen rather than quitting while the popup is open
+        actions.sort_by_key(|&action| action == Action::Quit);
+    }
+    for action in actions {
+        if handle(action, app) {
+            break;
         }
-        // Exit application on `Ctrl-C`
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
+    }
+    Ok(())
+}
+
+/// Actions while playing or analysing. Returns whether `action` applies here.
+fn handle_play_action(action: Action, app: &mut App) -> bool {
+    // Any other action calls off a resignation waiting to be confirmed
+    if action != Action::Resign {
+        app.confirm_resign = false;
+    }
+    match action {
+        // Cancel the selection, then leave analysis, exit once nothing is left
+        Action::Back => {
+            if app.game_state.selected().is_some() {
+                app.game_state.deselect();
+            } else if app.analysis.is_some() {
+                app.toggle_analysis();
+            } else {
                 app.quit();
             }
         }
-        // Counter handlers
-        // KeyCode::Right => {
-        //     app.increment_counter();
-        // }
-        // KeyCode::Left => {
-        //     app.decrement_counter();
-        // }
-        // KeyCode::Char('j') => {
-        //     app.decrement_counter();
-        // },
-        // KeyCode::Char('k') => {
-        //     app.increment_counter();
-        // }
-        // Other handlers you could add here.
-        _ => {}
+        Action::Quit => app.quit(),
+        // Cursor movement
+        Action::Left => move_cursor(app, 0, -1),
+        Action::Down => move_cursor(app, 1, 0),
+        Action::Up => move_cursor(app, -1, 0),
+        Action::Right => move_cursor(app, 0, 1),
+        // Select or drop the piece under the cursor
+        Action::Select => select_or_drop(app),
+        // History navigation
+        Action::Redo => app.redo(),
+        Action::Undo => app.undo(),
+        Action::First => app.jump_to(0),
+        Action::Last => app.jump_to(app.history.plies().len()),
+        // Save the game as PGN
+        Action::Save => app.save_pgn(),
+        // Show the position as FEN
+        Action::Fen => app.show_fen(),
+        // Enter or leave analysis mode
+        Action::Analysis => app.toggle_analysis(),
+        // Turn the board around
+        Action::Flip => app.flip_board(),
+        // Switch to the next colour theme
+        Action::Theme => app.next_theme(),
+        // Resign, and answer or make draw offers and claims
+        Action::Resign => app.perform(GameAction::Resign),
+        Action::OfferDraw => app.perform(GameAction::OfferDraw),
+        Action::AcceptDraw => app.perform(GameAction::AcceptDraw),
+        Action::DeclineDraw => app.perform(GameAction::DeclineDraw),
+        Action::ClaimDraw => app.perform(GameAction::ClaimDraw),
+        _ => return false,
     }
-    Ok(())
+    true
 }
 
-pub fn handle_mouse_events(mouse_event: MouseEvent, app: &mut App) -> Result<()> {
-    match mouse_event.kind {
-        MouseEventKind::Up(MouseButton::Left) => {
-            let x = mouse_event.column;
-            let y = mouse_event.row;
-            let (size_x, size_y) = size().unwrap();
-            let offset_x = (size_x - 40)/2;
-            let offset_y = (size_y - 24)/2;
-            if x < offset_x || y < offset_y || x > offset_x + 40 || y > offset_y + 24 {
-                app.game_state.selected = None;
-                return Ok(());
-            }
+/// Actions while the promotion popup asks for a piece, ignoring all others.
+fn handle_promotion_action(action: Action, app: &mut App) -> bool {
+    let Some(choice) = app.promotion.as_ref().map(|promotion| promotion.choice) else {
+        return false;
+    };
+    let mut highlight = |choice| {
+        if let Some(promotion) = &mut app.promotion {
+            promotion.choice = choice;
+        }
+    };
+    match action {
+        Action::Queen => app.promote(Piece::Queen),
+        Action::Rook => app.promote(Piece::Rook),
+        Action::Bishop => app.promote(Piece::Bishop),
+        Action::Knight => app.promote(Piece::Knight),
+        // Highlight a piece and pick it
+        Action::Left | Action::Up => highlight(choice.saturating_sub(1)),
+        Action::Right | Action::Down => highlight((choice + 1).min(PROMOTION_PIECES.len() - 1)),
+        Action::Select => app.promote(PROMOTION_PIECES[choice]),
+        Action::Back => app.cancel_promotion(),
+        Action::Quit => app.quit(),
+        _ => return false,
+    }
+    true
+}
+
+/// Actions once the game is over, when the board no longer takes moves.
+fn handle_game_over_action(action: Action, app: &mut App) -> bool {
+    match action {
+        // Hide the result banner, exit once it is hidden
+        Action::Back if app.banner => app.banner = false,
+        Action::Back | Action::Quit => app.quit(),
+        Action::NewGame => app.new_game(false),
+        Action::Rematch => app.new_game(true),
+        Action::Review => app.review(),
+        Action::Analysis => app.toggle_analysis(),
+        Action::Flip => app.flip_board(),
+        Action::Theme => app.next_theme(),
+        Action::Save => app.save_pgn(),
+        Action::Fen => app.show_fen(),
+        _ => return false,
+    }
+    true
+}
+
+/// Actions of the PGN viewer, which steps through the games instead of playing moves.
+fn handle_viewer_action(action: Action, app: &mut App) -> bool {
+    let index = app.viewer.as_ref().map_or(0, |viewer| viewer.index);
+    match action {
+        // Reviewing a finished game goes back to its result
+        Action::Back if app.outcome.is_some() => app.end_review(),
+        Action::Back | Action::Quit => app.quit(),
+        Action::Left | Action::Undo => app.undo(),
+        Action::Right | Action::Select | Action::Redo => app.redo(),
+        Action::First => app.jump_to(0),
+        Action::Last => app.jump_to(app.history.plies().len()),
+        Action::PreviousGame => app.show_game(index.wrapping_sub(1)),
+        Action::NextGame => app.show_game(index + 1),
+        Action::Analysis => app.toggle_analysis(),
+        Action::Flip => app.flip_board(),
+        Action::Theme => app.next_theme(),
+        _ => return false,
+    }
+    true
+}
+
+/// Moves the keyboard cursor in screen directions, showing it on the side to
+/// move's king if it was hidden.
+fn move_cursor(app: &mut App, d_row: i16, d_col: i16) {
+    let Some((row, col)) = app.game_state.cursor else {
+        app.game_state.cursor = Some(default_cursor(app));
+        return;
+    };
+    let (d_row, d_col) = if app.game_state.flipped { (-d_row, -d_col) } else { (d_row, d_col) };
+    let row = (row as i16 + d_row).clamp(0, 7) as u16;
+    let col = (col as i16 + d_col).clamp(0, 7) as u16;
+    app.game_state.cursor = Some((row, col));
+}
+
+fn default_cursor(app: &App) -> (u16, u16) {
+    let board = app.game.0.current_position();
+    let king = board.king_square(board.side_to_move());
+    (7 - king.get_rank().to_index() as u16, king.get_file().to_index() as u16)
+}
+
+/// Selects the square under the cursor, or plays the selected piece onto it.
+fn select_or_drop(app: &mut App) {
+    let Some(cursor) = app.game_state.cursor else {
+        app.game_state.cursor = Some(default_cursor(app));
+        return;
+    };
+    select_or_play(app, cursor);
+}
+
+/// Whether the player may pick up the piece at board `(row, col)`, which must
+/// be the side to move's and not the computer's, who is told to wait.
+fn own_piece(app: &mut App, (row, col): (u16, u16)) -> bool {
+    let board = app.game.0.current_position();
+    if board.color_on(to_square(row, col)) != Some(board.side_to_move()) {
+        return false;
+    }
+    if !app.human_to_move() {
+        app.wait_for_computer();
+        return false;
+    }
+    true
+}
 
-            let col = (x - offset_x)/5;
-            let row = (y - offset_y)/3;
+/// Selects the piece on `square`, with the moves it has in the current position.
+fn select(app: &mut App, square: (u16, u16)) {
+    let board = app.game.0.current_position();
+    app.game_state.select(square, &board);
+}
 
-            if col >= 8 || row >= 8 {
-                app.game_state.selected = None;
-                return Ok(());
+/// Selects the side to move's piece on `square`, or plays the selected piece
+/// onto it. Selecting the selected piece again drops it.
+fn select_or_play(app: &mut App, square: (u16, u16)) {
+    match app.game_state.selected() {
+        Some(selected) if selected == square => app.game_state.deselect(),
+        // picking another piece of one's own switches to it
+        _ if own_piece(app, square) => select(app, square),
+        Some(selected) => {
+            play_onto(app, selected, square);
+        }
+        None => {}
+    }
+}
+
+pub fn handle_mouse_events(mouse_event: MouseEvent, app: &mut App) -> Result<()> {
+    let x = mouse_event.column;
+    let y = mouse_event.row;
+    // Clicking a move in the move list goes to the position after it, where
+    // the keys could step there too
+    if mouse_event.kind == MouseEventKind::Up(MouseButton::Left) && app.promotion.is_none() {
+        let navigable = app.viewer.is_some() || app.outcome.is_none() || app.analysis.is_some();
+        if let Some(&(_, ply)) = app.move_buttons.iter().find(|(area, _)| inside(*area, x, y)) {
+            if navigable {
+                app.jump_to(ply);
             }
-            
-            if let Some((s_y, s_x)) = app.game_state.selected {
-                let start_square = Square::from_str(&app.game_state.to_chess_notation().unwrap()[..]).unwrap();
-
-                if let Some(piece) = app.game.0.current_position().piece_on(start_square) {
-                    let end_square   = Square::from_str(&to_chess_notation(row, col)[..]).unwrap();
-
-                    let current_move = ChessMove::new(start_square, end_square, None);
-
-                    if app.game.0.current_position().legal(current_move) {
-                        app.game.0.make_move(current_move);
-                        return Ok(());
-                    }
-
-                    if piece == Piece::Pawn {
-                        let offset_x = offset_x + (s_x * 5);
-                        let offset_y = offset_y + (s_y * 3);
-
-                        let offset_x = if s_x > col {
-                            offset_x - 5
-                        } else if s_x < col {
-                            offset_x + 5
-                        } else {
-                            offset_x + 0
-                        };
-
-                        let board = app.game.0.current_position();
-
-                        let offset_y = if board.color_on(start_square).unwrap() == chess::Color::White {
-                            offset_y - 3
-                        } else {
-                            offset_y + 3
-                        };
-
-                        if (start_square.get_rank() == Rank::Seventh && board.color_on(start_square).unwrap() == chess::Color::White) ||
-                           (start_square.get_rank() == Rank::Second && board.color_on(start_square).unwrap() == chess::Color::Black) {
-                            if y == offset_y && x == offset_x + 1 {
-                                let promotion_move = ChessMove::new(start_square, end_square, Some(Piece::Queen));
-                                if board.legal(promotion_move) {
-                                    app.game.0.make_move(promotion_move);
-                                    return Ok(());
-                                }
-                            } else if y == offset_y && x == offset_x + 3 {
-                                let promotion_move = ChessMove::new(start_square, end_square, Some(Piece::Rook));
-                                if board.legal(promotion_move) {
-                                    app.game.0.make_move(promotion_move);
-                                    return Ok(());
-                                }
-                            } else if y == offset_y + 2 && x == offset_x + 1 {
-                                let promotion_move = ChessMove::new(start_square, end_square, Some(Piece::Knight));
-                                if board.legal(promotion_move) {
-                                    app.game.0.make_move(promotion_move);
-                                    return Ok(());
-                                }
-                            } else if y == offset_y + 2 && x == offset_x + 3 {
-                                let promotion_move = ChessMove::new(start_square, end_square, Some(Piece::Bishop));
-                                if board.legal(promotion_move) {
-                                    app.game.0.make_move(promotion_move);
-                                    return Ok(());
-                                }
-                            } 
-                        }
-                    }
+            app.game_state.drag = None;
+            return Ok(());
+        }
+    }
+    if (app.viewer.is_some() || app.outcome.is_some()) && app.analysis.is_none() {
+        return Ok(());
+    }
+    if app.promotion.is_some() {
+        if mouse_event.kind == MouseEventKind::Up(MouseButton::Left) {
+            click_promotion(app, x, y);
+        }
+        return Ok(());
+    }
+    match mouse_event.kind {
+        // Pressing on a piece of the side to move may start dragging it
+        MouseEventKind::Down(MouseButton::Left) => {
+            app.game_state.drag = app.game_state.square_at(x, y)
+                .filter(|&square| own_piece(app, square))
+                .map(|from| Drag { from, at: (x, y), moved: false });
+        }
+        MouseEventKind::Drag(MouseButton::Left) => {
+            let under = app.game_state.square_at(x, y);
+            if let Some(drag) = &mut app.game_state.drag {
+                drag.at = (x, y);
+                // the drag only counts once the pointer leaves the piece's square
+                if !drag.moved && under != Some(drag.from) {
+                    drag.moved = true;
+                    let from = drag.from;
+                    select(app, from);
                 }
             }
-            
-            app.game_state.selected = Some((row, col));
-
+        }
+        MouseEventKind::Up(MouseButton::Left) => match app.game_state.drag.take() {
+            Some(drag) if drag.moved => drop_piece(app, drag.from, x, y),
+            _ => click(app, x, y),
         },
-        _ => (),
+        _ => {}
     }
     Ok(())
 }
+
+/// Drops the piece dragged from `from` at `(x, y)`, cancelling the move
+/// unless it is legal there.
+fn drop_piece(app: &mut App, from: (u16, u16), x: u16, y: u16) {
+    let played = app.game_state.square_at(x, y).is_some_and(|to| play_onto(app, from, to));
+    if !played {
+        app.game_state.deselect();
+    }
+}
+
+/// Clicks a button, selects the clicked square, or plays the selected piece onto it.
+fn click(app: &mut App, x: u16, y: u16) {
+    let button = app.buttons.iter().find(|(area, _)| inside(*area, x, y));
+    if let Some(&(_, action)) = button {
+        app.perform(action);
+        return;
+    }
+    match app.game_state.square_at(x, y) {
+        Some(square) => select_or_play(app, square),
+        None => app.game_state.deselect(),
+    }
+}
+
+/// Whether the cell at `(x, y)` lies in `area`.
+fn inside(area: Rect, x: u16, y: u16) -> bool {
+    (area.left()..area.right()).contains(&x) && (area.top()..area.bottom()).contains(&y)
+}
+
+/// Picks the piece clicked in the promotion popup, or closes it when the
+/// click misses the popup.
+fn click_promotion(app: &mut App, x: u16, y: u16) {
+    let Some(promotion) = &app.promotion else {
+        return;
+    };
+    if let Some(&(_, piece)) = promotion.tiles.iter().find(|&&(tile, _)| inside(tile, x, y)) {
+        app.promote(piece);
+    } else if !inside(promotion.area, x, y) {
+        app.cancel_promotion();
+    }
+}
+
+/// Tries to play the selected piece, on `from`, onto `to`, asking for the
+/// piece when a pawn promotes, and otherwise saying why the move is illegal.
+///
+/// Returns whether the move was played or waits in the promotion popup.
+fn play_onto(app: &mut App, from: (u16, u16), to: (u16, u16)) -> bool {
+    let start_square = to_square(from.0, from.1);
+    let end_square = to_square(to.0, to.1);
+    if !app.game_state.can_move_to(to) {
+        if from != to {
+            app.explain_illegal(start_square, end_square);
+        }
+        return false;
+    }
+    app.try_move(ChessMove::new(start_square, end_square, None)) || app.start_promotion(start_square, end_square)
+}
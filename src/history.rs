@@ -0,0 +1,336 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chess::{Board, ChessMove, Color, Game, Piece};
+
+use crate::game::MyGame;
+use crate::san;
+
+/// A single half-move and the position it produced.
+#[derive(Debug, Clone, Copy)]
+pub struct Ply {
+    pub chess_move: ChessMove,
+    pub position: Board,
+}
+
+/// Move history of a game, supporting undo, redo and jumping between plies.
+///
+/// `chess::Game` cannot take moves back, so the game is rebuilt from the
+/// starting position whenever the current ply changes.
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    /// position before the first move
+    start: Board,
+    /// halfmove clock of the starting position
+    start_halfmoves: u32,
+    /// fullmove number of the starting position
+    start_fullmove: u32,
+    /// every move played, including the redo tail
+    plies: Vec<Ply>,
+    /// SAN of each ply, kept in step with `plies` so the move list is not
+    /// rebuilt on every redraw
+    sans: Vec<String>,
+    /// number of plies currently applied
+    current: usize,
+}
+
+/// Checks that the piece placement of `fen` has one king of each colour.
+///
+/// `chess` reads out of bounds setting up a position without them, so this
+/// has to come before [`Board::from_str`].
+pub fn check_kings(fen: &str) -> Result<()> {
+    let placement = fen.split_whitespace().next().unwrap_or_default();
+    for (king, color) in [('K', "white"), ('k', "black")] {
+        let count = placement.chars().filter(|&c| c == king).count();
+        if count != 1 {
+            return Err(anyhow!("a position needs one {color} king, not {count}"));
+        }
+    }
+    Ok(())
+}
+
+impl Default for GameHistory {
+    fn default() -> Self {
+        Self::new(Board::default())
+    }
+}
+
+impl GameHistory {
+    /// Constructs an empty history starting from `start`.
+    pub fn new(start: Board) -> Self {
+        Self { start, start_halfmoves: 0, start_fullmove: 1, plies: Vec::new(), sans: Vec::new(), current: 0 }
+    }
+
+    /// Constructs an empty history starting from the position in `fen`.
+    ///
+    /// The move counters are optional and default to `0 1`.
+    pub fn from_fen(fen: &str) -> Result<Self> {
+        check_kings(fen)?;
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let start = Board::from_str(&fields.join(" ")).map_err(|e| anyhow!("{e}"))?;
+        let counter = |i: usize, name: &str| match fields.get(i) {
+            Some(field) => field.parse::<u32>().map_err(|_| anyhow!("invalid {name} `{field}` in FEN")),
+            None => Ok(if i == 4 { 0 } else { 1 }),
+        };
+        let start_halfmoves = counter(4, "halfmove clock")?;
+        let start_fullmove = counter(5, "fullmove number")?.max(1);
+        Ok(Self { start_halfmoves, start_fullmove, ..Self::new(start) })
+    }
+
+    /// Fullmove number of the first move.
+    pub fn first_move_number(&self) -> u32 {
+        self.start_fullmove
+    }
+
+    /// FEN of the position after `ply` plies, with its move counters.
+    pub fn fen_at(&self, ply: usize) -> String {
+        let board = self.position_at(ply);
+        let halfmoves = self.halfmoves_at(ply);
+        let fullmove = self.move_number_at(ply);
+
+        // the board prints its FEN with placeholder move counters
+        let fen = board.to_string();
+        let position = fen.rsplitn(3, ' ').last().unwrap_or(&fen);
+        format!("{position} {halfmoves} {fullmove}")
+    }
+
+    /// Halfmove clock of the position after `ply` plies: plies since the last
+    /// capture or pawn move.
+    pub fn halfmoves_at(&self, ply: usize) -> u32 {
+        let mut board = self.start;
+        let mut halfmoves = self.start_halfmoves;
+        for p in &self.plies[..ply] {
+            let m = p.chess_move;
+            if board.piece_on(m.get_source()) == Some(Piece::Pawn) || board.piece_on(m.get_dest()).is_some() {
+                halfmoves = 0;
+            } else {
+                halfmoves += 1;
+            }
+            board = p.position;
+        }
+        halfmoves
+    }
+
+    /// How often the current position has occurred, counting itself.
+    pub fn repetitions(&self) -> usize {
+        let hash = self.position().get_hash();
+        (0..=self.current).filter(|&ply| self.position_at(ply).get_hash() == hash).count()
+    }
+
+    /// Whether a draw can be claimed in the current position: it has occurred
+    /// three times, or fifty moves passed without a capture or pawn move.
+    pub fn can_claim_draw(&self) -> bool {
+        self.repetitions() >= 3 || self.halfmoves_at(self.current) >= 100
+    }
+
+    /// Fullmove number of the position after `ply` plies.
+    pub fn move_number_at(&self, ply: usize) -> u32 {
+        let black_first = u32::from(self.start.side_to_move() == Color::Black);
+        self.start_fullmove + (ply as u32 + black_first) / 2
+    }
+
+    /// FEN of the current position.
+    pub fn fen(&self) -> String {
+        self.fen_at(self.current)
+    }
+
+    /// The position before the first move.
+    pub fn start(&self) -> Board {
+        self.start
+    }
+
+    /// Every recorded ply, including any that were undone.
+    pub fn plies(&self) -> &[Ply] {
+        &self.plies
+    }
+
+    /// SAN of every recorded ply, including any that were undone.
+    pub fn sans(&self) -> &[String] {
+        &self.sans
+    }
+
+    /// Number of plies currently applied.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// The position at the current ply.
+    pub fn position(&self) -> Board {
+        self.position_at(self.current)
+    }
+
+    /// The position after `ply` plies.
+    pub fn position_at(&self, ply: usize) -> Board {
+        match ply {
+            0 => self.start,
+            n => self.plies[n - 1].position,
+        }
+    }
+
+    /// An empty history from the same starting position.
+    pub fn restarted(&self) -> Self {
+        Self { plies: Vec::new(), sans: Vec::new(), current: 0, ..self.clone() }
+    }
+
+    /// Records `chess_move` as played from the current position.
+    ///
+    /// Playing the move the redo tail continues with keeps the tail,
+    /// any other move drops it.
+    pub fn push(&mut self, chess_move: ChessMove) {
+        if self.plies.get(self.current).map(|ply| ply.chess_move) != Some(chess_move) {
+            let before = self.position();
+            let position = before.make_move_new(chess_move);
+            self.plies.truncate(self.current);
+            self.sans.truncate(self.current);
+            self.plies.push(Ply { chess_move, position });
+            self.sans.push(san::to_san(&before, chess_move));
+        }
+        self.current += 1;
+    }
+
+    /// Steps back one ply. Returns whether anything was undone.
+    pub fn undo(&mut self) -> bool {
+        self.jump(self.current.wrapping_sub(1))
+    }
+
+    /// Steps forward one ply. Returns whether anything was redone.
+    pub fn redo(&mut self) -> bool {
+        self.jump(self.current + 1)
+    }
+
+    /// Moves to `ply`, where 0 is the starting position.
+    ///
+    /// Returns whether the current ply changed.
+    pub fn jump(&mut self, ply: usize) -> bool {
+        if ply > self.plies.len() || ply == self.current {
+            return false;
+        }
+        self.current = ply;
+        true
+    }
+
+    /// Builds a game holding the moves up to the current ply.
+    pub fn game(&self) -> MyGame {
+        let mut game = Game::new_with_board(self.start);
+        for ply in &self.plies[..self.current] {
+            game.make_move(ply.chess_move);
+        }
+        MyGame(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(uci: &str) -> ChessMove {
+        ChessMove::from_str(uci).unwrap()
+    }
+
+    fn played(moves: &[&str]) -> GameHistory {
+        let mut history = GameHistory::default();
+        for uci in moves {
+            history.push(mv(uci));
+        }
+        history
+    }
+
+    #[test]
+    fn undo_then_redo_restores_the_position() {
+        let mut history = played(&["e2e4", "e7e5", "g1f3"]);
+        let position = history.position();
+        assert!(history.undo());
+        assert!(history.undo());
+        assert_eq!(history.current(), 1);
+        assert_eq!(history.position(), Board::default().make_move_new(mv("e2e4")));
+        // undone plies are kept for redoing
+        assert_eq!(history.plies().len(), 3);
+        assert!(history.redo());
+        assert!(history.redo());
+        assert_eq!(history.position(), position);
+        assert!(!history.redo());
+        assert_eq!(history.game().0.current_position(), position);
+    }
+
+    #[test]
+    fn undo_stops_at_the_start() {
+        let mut history = played(&["e2e4"]);
+        assert!(history.undo());
+        assert!(!history.undo());
+        assert_eq!(history.current(), 0);
+        assert_eq!(history.position(), Board::default());
+    }
+
+    #[test]
+    fn jump_stays_within_the_history() {
+        let mut history = played(&["e2e4", "e7e5", "g1f3"]);
+        assert!(history.jump(0));
+        assert_eq!(history.position(), Board::default());
+        assert!(!history.jump(0));
+        assert!(!history.jump(4));
+        assert!(!history.jump(usize::MAX));
+        assert_eq!(history.current(), 0);
+        assert!(history.jump(3));
+        assert_eq!(history.current(), 3);
+    }
+
+    #[test]
+    fn replaying_the_undone_move_keeps_the_redo_tail() {
+        let mut history = played(&["e2e4", "e7e5", "g1f3"]);
+        history.jump(1);
+        history.push(mv("e7e5"));
+        assert_eq!(history.current(), 2);
+        assert_eq!(history.plies().len(), 3);
+        assert!(history.redo());
+        assert_eq!(history.plies()[2].chess_move, mv("g1f3"));
+    }
+
+    #[test]
+    fn another_move_drops_the_redo_tail() {
+        let mut history = played(&["e2e4", "e7e5", "g1f3"]);
+        history.jump(1);
+        history.push(mv("c7c5"));
+        assert_eq!(history.current(), 2);
+        assert_eq!(history.plies().len(), 2);
+        assert!(!history.redo());
+        assert_eq!(history.position().piece_on(chess::Square::C5), Some(Piece::Pawn));
+    }
+
+    #[test]
+    fn sans_follow_the_plies() {
+        let mut history = played(&["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(history.sans(), ["e4", "e5", "Nf3"]);
+        history.undo();
+        history.undo();
+        assert_eq!(history.sans(), ["e4", "e5", "Nf3"]);
+        history.push(mv("e7e5"));
+        assert_eq!(history.sans(), ["e4", "e5", "Nf3"]);
+        history.undo();
+        history.push(mv("c7c5"));
+        assert_eq!(history.sans(), ["e4", "c5"]);
+        assert!(history.restarted().sans().is_empty());
+    }
+
+    #[test]
+    fn from_fen_needs_one_king_each() {
+        for fen in [
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/4K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+        ] {
+            assert!(GameHistory::from_fen(fen).is_err(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn from_fen_reads_the_move_counters() {
+        let history = GameHistory::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 12 40").unwrap();
+        assert_eq!(history.halfmoves_at(0), 12);
+        assert_eq!(history.first_move_number(), 40);
+
+        let history = GameHistory::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(history.halfmoves_at(0), 0);
+        assert_eq!(history.first_move_number(), 1);
+    }
+}
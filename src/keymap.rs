@@ -0,0 +1,383 @@
+//! Key bindings: which key chords trigger which actions.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+
+/// Something a key can be bound to. What an action does can depend on the
+/// screen, e.g. `back` cancels a selection in play and closes the result banner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    /// cancel, close or leave whatever is open, quitting when nothing is
+    Back,
+    Left,
+    Down,
+    Up,
+    Right,
+    /// select the square under the cursor or move onto it
+    Select,
+    Undo,
+    Redo,
+    /// jump to the start of the game
+    First,
+    /// jump to the latest move
+    Last,
+    Save,
+    Fen,
+    Analysis,
+    Flip,
+    Theme,
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    ClaimDraw,
+    NewGame,
+    Rematch,
+    Review,
+    PreviousGame,
+    NextGame,
+    /// promote to a queen, in the promotion popup
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+}
+
+/// Bindings used for actions the configuration leaves out.
+const DEFAULT_KEYS: [(Action, &[&str]); 30] = [
+    (Action::Quit, &["q", "ctrl-c"]),
+    (Action::Back, &["esc"]),
+    (Action::Left, &["left", "h"]),
+    (Action::Down, &["down", "j"]),
+    (Action::Up, &["up", "k"]),
+    (Action::Right, &["right", "l"]),
+    (Action::Select, &["enter", "space"]),
+    (Action::Undo, &["u", "backspace"]),
+    (Action::Redo, &["ctrl-r"]),
+    (Action::First, &["home"]),
+    (Action::Last, &["end"]),
+    (Action::Save, &["s"]),
+    (Action::Fen, &["f"]),
+    (Action::Analysis, &["a"]),
+    (Action::Flip, &["b"]),
+    (Action::Theme, &["t"]),
+    (Action::Resign, &["x"]),
+    (Action::OfferDraw, &["o"]),
+    (Action::AcceptDraw, &["y"]),
+    (Action::DeclineDraw, &["n"]),
+    (Action::ClaimDraw, &["c"]),
+    (Action::NewGame, &["n"]),
+    (Action::Rematch, &["r"]),
+    (Action::Review, &["v"]),
+    (Action::PreviousGame, &["pageup", "p"]),
+    (Action::NextGame, &["pagedown", "n"]),
+    (Action::Queen, &["q"]),
+    (Action::Rook, &["r"]),
+    (Action::Bishop, &["b"]),
+    (Action::Knight, &["n"]),
+];
+
+/// A key with its modifiers, written like `ctrl-r`, `esc` or `x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Whether `key_event` is this chord. Shift is part of the character
+    /// typed, so it is only compared for keys other than characters.
+    pub fn matches(&self, key_event: &KeyEvent) -> bool {
+        match (self.code, key_event.code) {
+            (KeyCode::Char(bound), KeyCode::Char(typed)) => {
+                let modifiers = key_event.modifiers - KeyModifiers::SHIFT;
+                let same_char = if modifiers.is_empty() {
+                    bound == typed
+                } else {
+                    // terminals differ in the case they report with Ctrl and Alt
+                    bound.eq_ignore_ascii_case(&typed)
+                };
+                same_char && modifiers == self.modifiers - KeyModifiers::SHIFT
+            }
+            (bound, typed) => bound == typed && key_event.modifiers == self.modifiers,
+        }
+    }
+}
+
+/// Names of the keys that are not characters.
+const KEY_NAMES: [(&str, KeyCode); 15] = [
+    ("esc", KeyCode::Esc),
+    ("enter", KeyCode::Enter),
+    ("space", KeyCode::Char(' ')),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (modifier, length) = if lower.starts_with("ctrl-") {
+                (KeyModifiers::CONTROL, 5)
+            } else if lower.starts_with("alt-") {
+                (KeyModifiers::ALT, 4)
+            } else if lower.starts_with("shift-") {
+                (KeyModifiers::SHIFT, 6)
+            } else {
+                break;
+            };
+            modifiers |= modifier;
+            rest = &rest[length..];
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let name = rest.to_ascii_lowercase();
+                let function = name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                    .filter(|number| (1..=12).contains(number));
+                match function {
+                    Some(number) => KeyCode::F(number),
+                    None => KEY_NAMES
+                        .iter()
+                        .find(|(key, _)| *key == name)
+                        .map(|&(_, code)| code)
+                        .ok_or_else(|| anyhow!("unknown key `{rest}` in `{s}`"))?,
+                }
+            }
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(c) if c != ' ' => write!(f, "{c}"),
+            KeyCode::F(number) => write!(f, "f{number}"),
+            code => {
+                let name = KEY_NAMES.iter().find(|(_, key)| *key == code).map_or("?", |(name, _)| name);
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let chord = String::deserialize(deserializer)?;
+        chord.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The chords bound to one action, written as one chord or a list of them.
+struct Chords(Vec<KeyChord>);
+
+impl<'de> Deserialize<'de> for Chords {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChordsVisitor;
+
+        impl<'de> Visitor<'de> for ChordsVisitor {
+            type Value = Chords;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a key such as \"ctrl-r\" or a list of keys")
+            }
+
+            fn visit_str<E: de::Error>(self, chord: &str) -> Result<Chords, E> {
+                chord.parse().map(|chord| Chords(vec![chord])).map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Chords, A::Error> {
+                let mut chords = Vec::new();
+                while let Some(chord) = seq.next_element()? {
+                    chords.push(chord);
+                }
+                Ok(Chords(chords))
+            }
+        }
+
+        deserializer.deserialize_any(ChordsVisitor)
+    }
+}
+
+/// Chords bound to each action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeyChord>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_KEYS
+            .iter()
+            .map(|&(action, chords)| (action, chords.iter().map(|chord| chord.parse().unwrap()).collect()))
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl<'de> Deserialize<'de> for Keymap {
+    /// Reads a table of actions to chords, keeping the default bindings of
+    /// the actions it leaves out.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = HashMap::<Action, Chords>::deserialize(deserializer)?;
+        let mut keymap = Keymap::default();
+        for (action, chords) in &mut keymap.bindings {
+            if let Some(Chords(bound)) = table.get(action) {
+                chords.clone_from(bound);
+            }
+        }
+        Ok(keymap)
+    }
+}
+
+impl Keymap {
+    /// Actions bound to `key_event`, in the order they are listed.
+    pub fn actions(&self, key_event: &KeyEvent) -> Vec<Action> {
+        self.bindings
+            .iter()
+            .filter(|(_, chords)| chords.iter().any(|chord| chord.matches(key_event)))
+            .map(|&(action, _)| action)
+            .collect()
+    }
+
+    /// First chord bound to `action`, for showing next to buttons.
+    pub fn hint(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .find(|&&(bound, _)| bound == action)
+            .and_then(|(_, chords)| chords.first())
+            .map_or_else(|| "-".to_owned(), ToString::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> KeyChord {
+        text.parse().unwrap()
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_chords() {
+        assert_eq!(chord("x"), KeyChord { code: KeyCode::Char('x'), modifiers: KeyModifiers::NONE });
+        assert_eq!(chord("ctrl-r").modifiers, KeyModifiers::CONTROL);
+        assert_eq!(chord("Alt-x").modifiers, KeyModifiers::ALT);
+        assert_eq!(chord("shift-tab"), KeyChord { code: KeyCode::Tab, modifiers: KeyModifiers::SHIFT });
+        assert_eq!(chord("ctrl-alt-delete").modifiers, KeyModifiers::CONTROL | KeyModifiers::ALT);
+        assert_eq!(chord("PageUp").code, KeyCode::PageUp);
+        assert_eq!(chord("space").code, KeyCode::Char(' '));
+        assert_eq!(chord("-").code, KeyCode::Char('-'));
+        assert_eq!(chord("f1").code, KeyCode::F(1));
+        assert_eq!(chord("ctrl-F12"), KeyChord { code: KeyCode::F(12), modifiers: KeyModifiers::CONTROL });
+        // a lone `f` is the letter
+        assert_eq!(chord("f").code, KeyCode::Char('f'));
+    }
+
+    #[test]
+    fn rejects_bad_chords() {
+        for text in ["", "ctrl-", "f0", "f13", "escape", "xy", "super-x", "ctrl+r"] {
+            assert!(text.parse::<KeyChord>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn chords_print_as_they_are_written() {
+        for text in ["x", "ctrl-r", "alt-shift-left", "space", "f5", "pagedown"] {
+            assert_eq!(chord(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn matches_key_events() {
+        let ctrl_r = chord("ctrl-r");
+        assert!(ctrl_r.matches(&key(KeyCode::Char('r'), KeyModifiers::CONTROL)));
+        assert!(ctrl_r.matches(&key(KeyCode::Char('R'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)));
+        assert!(!ctrl_r.matches(&key(KeyCode::Char('r'), KeyModifiers::NONE)));
+        assert!(!ctrl_r.matches(&key(KeyCode::Char('r'), KeyModifiers::ALT)));
+
+        // shift is part of the character typed
+        assert!(chord("N").matches(&key(KeyCode::Char('N'), KeyModifiers::SHIFT)));
+        assert!(!chord("n").matches(&key(KeyCode::Char('N'), KeyModifiers::SHIFT)));
+        assert!(!chord("N").matches(&key(KeyCode::Char('n'), KeyModifiers::NONE)));
+
+        // but not of other keys
+        assert!(chord("shift-tab").matches(&key(KeyCode::Tab, KeyModifiers::SHIFT)));
+        assert!(!chord("tab").matches(&key(KeyCode::Tab, KeyModifiers::SHIFT)));
+        assert!(chord("f2").matches(&key(KeyCode::F(2), KeyModifiers::NONE)));
+        assert!(!chord("f2").matches(&key(KeyCode::F(3), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn finds_every_action_of_a_key() {
+        let keymap = Keymap::default();
+        let n = key(KeyCode::Char('n'), KeyModifiers::NONE);
+        let actions = keymap.actions(&n);
+        assert_eq!(actions, [Action::DeclineDraw, Action::NewGame, Action::NextGame, Action::Knight]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('r'), KeyModifiers::CONTROL)), [Action::Redo]);
+        assert!(keymap.actions(&key(KeyCode::Char('z'), KeyModifiers::NONE)).is_empty());
+        assert_eq!(keymap.hint(Action::Undo), "u");
+    }
+
+    #[test]
+    fn keys_table_replaces_only_the_defaults_it_names() {
+        let keymap: Keymap = toml::from_str("undo = \"z\"\nquit = [\"ctrl-q\", \"f10\"]").unwrap();
+        let z = key(KeyCode::Char('z'), KeyModifiers::NONE);
+        assert_eq!(keymap.actions(&z), [Action::Undo]);
+        assert!(keymap.actions(&key(KeyCode::Char('u'), KeyModifiers::NONE)).is_empty());
+        assert_eq!(keymap.actions(&key(KeyCode::F(10), KeyModifiers::NONE)), [Action::Quit]);
+        // `q` still picks a queen, but no longer quits
+        assert_eq!(keymap.actions(&key(KeyCode::Char('q'), KeyModifiers::NONE)), [Action::Queen]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('r'), KeyModifiers::CONTROL)), [Action::Redo]);
+        assert_eq!(keymap.hint(Action::Quit), "ctrl-q");
+    }
+
+    #[test]
+    fn keys_table_rejects_mistakes() {
+        assert!(toml::from_str::<Keymap>("jump = \"j\"").is_err());
+        assert!(toml::from_str::<Keymap>("undo = \"ctrl-\"").is_err());
+        assert!(toml::from_str::<Keymap>("undo = 3").is_err());
+        // an empty list unbinds the action
+        let keymap: Keymap = toml::from_str("save = []").unwrap();
+        assert_eq!(keymap.hint(Action::Save), "-");
+    }
+}
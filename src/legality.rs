@@ -0,0 +1,222 @@
+//! Why a move is illegal, worked out from the position so the player can be
+//! told what is wrong with it.
+
+use chess::{
+    between, get_bishop_moves, get_bishop_rays, get_king_moves, get_knight_moves, get_pawn_attacks,
+    get_pawn_quiets, get_rook_moves, get_rook_rays, BitBoard, Board, ChessMove, Color, File, Piece, Square,
+    EMPTY,
+};
+
+/// Whether `by` attacks `square` once the pieces on `occupied` block lines
+/// and the pieces on `captured` are gone.
+fn attacked(board: &Board, square: Square, by: Color, occupied: BitBoard, captured: BitBoard) -> bool {
+    let attackers = board.color_combined(by) & !captured;
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let straight = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let hits = (get_bishop_moves(square, occupied) & diagonal)
+        | (get_rook_moves(square, occupied) & straight)
+        | (get_knight_moves(square) & board.pieces(Piece::Knight))
+        | (get_king_moves(square) & board.pieces(Piece::King))
+        // pawns attack `square` from where a pawn of the other side on it would attack
+        | get_pawn_attacks(square, !by, *board.pieces(Piece::Pawn));
+    hits & attackers != EMPTY
+}
+
+/// Why castling with the king on `from` to `to` is illegal.
+fn castling(board: &Board, from: Square, to: Square) -> &'static str {
+    let color = board.side_to_move();
+    let rights = board.castle_rights(color);
+    let (allowed, path) = if to.get_file() > from.get_file() {
+        (rights.has_kingside(), rights.kingside_squares(color))
+    } else {
+        (rights.has_queenside(), rights.queenside_squares(color))
+    };
+    let occupied = *board.combined();
+    if !allowed {
+        "king or rook has already moved"
+    } else if path & occupied != EMPTY {
+        "pieces are in the way of castling"
+    } else if *board.checkers() != EMPTY {
+        "cannot castle out of check"
+    } else if between(from, to).into_iter().any(|square| attacked(board, square, !color, occupied, EMPTY)) {
+        "castling through attacked square"
+    } else {
+        "king would be in check"
+    }
+}
+
+/// Why the piece on `from` cannot move to `to` in `board`, or `None` if it can.
+pub fn why_illegal(board: &Board, from: Square, to: Square) -> Option<&'static str> {
+    let legal = [None, Some(Piece::Queen)]
+        .into_iter()
+        .any(|promotion| board.legal(ChessMove::new(from, to, promotion)));
+    if legal {
+        return None;
+    }
+    let color = board.side_to_move();
+    let Some(piece) = board.piece_on(from) else {
+        return Some("no piece to move");
+    };
+    if board.color_on(from) != Some(color) {
+        return Some("not the side to move");
+    }
+    let own = *board.color_combined(color);
+    if own & BitBoard::from_square(to) != EMPTY {
+        return Some("square is taken by your own piece");
+    }
+
+    let home = Square::make_square(from.get_rank(), File::E);
+    let two_files = from.get_file().to_index().abs_diff(to.get_file().to_index()) == 2;
+    if piece == Piece::King && from == home && to.get_rank() == from.get_rank() && two_files {
+        return Some(castling(board, from, to));
+    }
+
+    // the squares the piece reaches, ignoring what it leaves its king open to
+    let occupied = *board.combined();
+    let en_passant = board.en_passant().map(|square| square.uforward(color));
+    let enemies = board.color_combined(!color) | en_passant.map_or(EMPTY, BitBoard::from_square);
+    let reach = match piece {
+        Piece::Pawn => get_pawn_quiets(from, color, occupied) | get_pawn_attacks(from, color, enemies),
+        Piece::Knight => get_knight_moves(from),
+        Piece::Bishop => get_bishop_moves(from, occupied),
+        Piece::Rook => get_rook_moves(from, occupied),
+        Piece::Queen => get_bishop_moves(from, occupied) | get_rook_moves(from, occupied),
+        Piece::King => get_king_moves(from),
+    };
+    if reach & BitBoard::from_square(to) == EMPTY {
+        let rays = match piece {
+            Piece::Bishop => get_bishop_rays(from),
+            Piece::Rook => get_rook_rays(from),
+            Piece::Queen => get_bishop_rays(from) | get_rook_rays(from),
+            _ => EMPTY,
+        };
+        return Some(if rays & BitBoard::from_square(to) != EMPTY {
+            "another piece is in the way"
+        } else {
+            match piece {
+                Piece::Pawn => "a pawn cannot move there",
+                Piece::Knight => "a knight cannot move there",
+                Piece::Bishop => "a bishop moves diagonally",
+                Piece::Rook => "a rook moves along ranks and files",
+                Piece::Queen => "a queen moves along lines and diagonals",
+                Piece::King => "a king moves one square at a time",
+            }
+        });
+    }
+
+    // the move is possible but for its own king
+    let mut captured = BitBoard::from_square(to);
+    if piece == Piece::Pawn && Some(to) == en_passant {
+        captured |= BitBoard::from_square(to.ubackward(color));
+    }
+    let after = (occupied & !BitBoard::from_square(from) & !captured) | BitBoard::from_square(to);
+    let king = if piece == Piece::King { to } else { board.king_square(color) };
+    Some(if !attacked(board, king, !color, after, captured) {
+        "not allowed here"
+    } else if piece == Piece::King {
+        "king would be in check"
+    } else if *board.checkers() != EMPTY {
+        "must address check"
+    } else {
+        "piece is pinned"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn why(fen: &str, from: Square, to: Square) -> Option<&'static str> {
+        why_illegal(&Board::from_str(fen).unwrap(), from, to)
+    }
+
+    #[test]
+    fn legal_moves_have_no_reason() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(why(start, Square::E2, Square::E4), None);
+        assert_eq!(why(start, Square::G1, Square::F3), None);
+        // promotions are legal whichever piece is picked later
+        assert_eq!(why("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", Square::A7, Square::A8), None);
+    }
+
+    #[test]
+    fn pinned_piece() {
+        let fen = "4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1";
+        assert_eq!(why(fen, Square::E2, Square::C3), Some("piece is pinned"));
+        // a pinned piece may still move along the pin
+        let fen = "4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1";
+        assert_eq!(why(fen, Square::E2, Square::E5), None);
+        assert_eq!(why(fen, Square::E2, Square::A2), Some("piece is pinned"));
+    }
+
+    #[test]
+    fn king_walking_into_check() {
+        let fen = "4k3/8/8/8/8/8/3r4/4K3 w - - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::F2), Some("king would be in check"));
+        // the king's own square is no shelter along the line it leaves
+        let fen = "4k3/8/8/8/8/8/8/r3K3 w - - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::F1), Some("king would be in check"));
+    }
+
+    #[test]
+    fn check_left_unanswered() {
+        let fen = "4k3/4r3/8/8/8/8/P7/4K3 w - - 0 1";
+        assert_eq!(why(fen, Square::A2, Square::A3), Some("must address check"));
+        assert_eq!(why(fen, Square::E1, Square::E2), Some("king would be in check"));
+        assert_eq!(why(fen, Square::E1, Square::D1), None);
+    }
+
+    #[test]
+    fn castling_out_of_check() {
+        let fen = "4k3/4r3/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::G1), Some("cannot castle out of check"));
+    }
+
+    #[test]
+    fn castling_through_an_attacked_square() {
+        let fen = "4k3/5r2/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::G1), Some("castling through attacked square"));
+        let fen = "r3k3/8/8/8/8/8/8/3RK3 b q - 0 1";
+        assert_eq!(why(fen, Square::E8, Square::C8), Some("castling through attacked square"));
+    }
+
+    #[test]
+    fn castling_into_check() {
+        let fen = "4k3/6r1/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::G1), Some("king would be in check"));
+    }
+
+    #[test]
+    fn castling_without_rights() {
+        let fen = "4k3/8/8/8/8/8/8/R3K2R w K - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::C1), Some("king or rook has already moved"));
+        assert_eq!(why(fen, Square::E1, Square::G1), None);
+        let fen = "4k3/8/8/8/8/8/8/4KB1R w K - 0 1";
+        assert_eq!(why(fen, Square::E1, Square::G1), Some("pieces are in the way of castling"));
+    }
+
+    #[test]
+    fn moves_the_piece_cannot_make() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(why(start, Square::E3, Square::E4), Some("no piece to move"));
+        assert_eq!(why(start, Square::E7, Square::E5), Some("not the side to move"));
+        assert_eq!(why(start, Square::D1, Square::D2), Some("square is taken by your own piece"));
+        assert_eq!(why(start, Square::C1, Square::A3), Some("another piece is in the way"));
+        assert_eq!(why(start, Square::E2, Square::E5), Some("a pawn cannot move there"));
+        assert_eq!(why(start, Square::G1, Square::G3), Some("a knight cannot move there"));
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(why(fen, Square::A1, Square::B2), Some("a rook moves along ranks and files"));
+        assert_eq!(why(fen, Square::E1, Square::E3), Some("a king moves one square at a time"));
+    }
+
+    #[test]
+    fn en_passant_can_expose_the_king() {
+        // taking en passant clears both pawns off the king's rank
+        let fen = "8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1";
+        assert_eq!(why(fen, Square::E5, Square::D6), Some("piece is pinned"));
+        let fen = "8/8/8/3pP3/8/8/8/K3k3 w - d6 0 1";
+        assert_eq!(why(fen, Square::E5, Square::D6), None);
+    }
+}
@@ -1,25 +1,102 @@
+pub mod analysis;
 pub mod app;
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod engine;
 pub mod game;
 pub mod event;
 pub mod ui;
+pub mod viewer;
 pub mod tui;
 pub mod handler;
+pub mod history;
+pub mod keymap;
+pub mod legality;
+pub mod material;
+pub mod san;
+pub mod pgn;
+pub mod uci;
+pub mod outcome;
+pub mod theme;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use analysis::AnalyserSetup;
 use app::App;
+use cli::Args;
+use config::Config;
+use engine::{Backend, Engine, Limits};
+use uci::UciEngine;
 use event::{Event, EventHandler};
 use ratatui::{backend::CrosstermBackend, Terminal};
 use tui::Tui;
 use handler::*;
 
 fn main() -> Result<()> {
+  let mut args = Args::parse()?;
+  // options given on the command line win over the configuration file
+  let config = Config::load()?;
+  args.time_control = args.time_control.or(config.time_control);
+  args.theme = args.theme.or(config.theme);
+  args.glyphs = args.glyphs.or(config.glyphs);
+  args.orientation = args.orientation.or(config.orientation);
+  args.colors = args.colors.or(config.colors);
+  args.engine = args.engine.or(config.engine);
+
   // Create an application.
   let mut app = App::new();
+  app.pgn_out = args.pgn_out;
+  app.keymap = config.keys;
+  // the player's side faces them unless asked otherwise
+  app.orientation = match (args.orientation, args.vs_computer) {
+    (Some(orientation), _) => orientation,
+    (None, Some(chess::Color::Black)) => game::Orientation::Black,
+    (None, _) => game::Orientation::White,
+  };
+  app.game_state.glyphs = args.glyphs.unwrap_or_else(game::Glyphs::detect);
+  app.color_depth = args.colors.unwrap_or_else(theme::ColorDepth::detect);
+  app.themes = theme::load_all()?;
+  if let Some(name) = &args.theme {
+    let Some(index) = app.themes.iter().position(|theme| theme.is_called(name)) else {
+      let names: Vec<_> = app.themes.iter().map(|theme| theme.id.as_str()).collect();
+      anyhow::bail!("no theme `{name}`, try one of {}", names.join(", "));
+    };
+    app.theme = index;
+  }
+  if let Some(control) = args.time_control {
+    app.set_time_control(control);
+  }
+  if let Some(fen) = &args.fen {
+    app.load_fen(fen).context("invalid --fen position")?;
+  }
+  if let Some(path) = &args.view {
+    let games = pgn::load(path, args.variations)?;
+    if games.is_empty() {
+      anyhow::bail!("no games in {}", path.display());
+    }
+    if args.game == 0 || args.game > games.len() {
+      anyhow::bail!("{} has {} game(s), there is no game {}", path.display(), games.len(), args.game);
+    }
+    app.view(games, args.game - 1);
+  }
 
   // Initialize the terminal user interface.
   let backend = CrosstermBackend::new(std::io::stderr());
   let terminal = Terminal::new(backend)?;
-  let events = EventHandler::new(250);
+  let events = EventHandler::new(config.tick_rate.unwrap_or(250));
+  if let Some(human) = args.vs_computer {
+    let backend = match &args.engine {
+      Some(path) => Backend::Uci(UciEngine::spawn(path, &[], &args.engine_options, events.sender(), Event::Uci)?),
+      None => Backend::Native(Engine::spawn(events.sender())),
+    };
+    app.set_computer(!human, Limits::level(args.level), backend);
+  }
+  app.analyser_setup = Some(AnalyserSetup {
+    engine: args.engine,
+    options: args.engine_options,
+    lines: args.lines,
+    events: events.sender(),
+  });
   let mut tui = Tui::new(terminal, events);
   tui.enter()?;
 
@@ -29,14 +106,24 @@ fn main() -> Result<()> {
     tui.draw(&mut app)?;
     // Handle events.
     match tui.events.next()? {
-      Event::Tick => {},
+      Event::Tick => app.tick(),
       Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
       Event::Mouse(mouse_event) => handle_mouse_events(mouse_event, &mut app)?,
       Event::Resize(_, _) => {},
+      Event::Paste(text) => app.paste(&text),
+      Event::Computer(id, chess_move) => app.computer_move(id, chess_move),
+      Event::Uci(message) => app.uci_message(message),
+      Event::Analysis(id, infos) => app.analysis_lines(id, infos),
+      Event::UciAnalysis(message) => app.uci_analysis(message),
     };
   }
 
   // Exit the user interface.
   tui.exit()?;
+
+  // Save the finished game if asked to.
+  if let Some(path) = &app.pgn_out {
+    app.write_pgn(path)?;
+  }
   Ok(())
 }
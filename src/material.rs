@@ -0,0 +1,117 @@
+//! Captured pieces, taken from the moves played, and the material balance of
+//! the pieces left on the board.
+
+use chess::{Board, Color, Piece};
+
+use crate::history::GameHistory;
+
+/// Pieces that can be captured, cheapest first, with their usual values.
+const VALUES: [(Piece, i32); 5] = [
+    (Piece::Pawn, 1),
+    (Piece::Knight, 3),
+    (Piece::Bishop, 3),
+    (Piece::Rook, 5),
+    (Piece::Queen, 9),
+];
+
+fn count(board: &Board, piece: Piece, color: Color) -> i32 {
+    (board.pieces(piece) & board.color_combined(color)).popcnt() as i32
+}
+
+/// Pieces of `color` captured in the plies of `history` up to its current
+/// one, cheapest first.
+///
+/// A promoted piece counts as what it became, and pieces already missing from
+/// a set-up starting position do not count at all.
+pub fn captured(history: &GameHistory, color: Color) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    for (ply, p) in history.plies()[..history.current()].iter().enumerate() {
+        let before = history.position_at(ply);
+        if before.side_to_move() == color {
+            continue;
+        }
+        let (source, dest) = (p.chess_move.get_source(), p.chess_move.get_dest());
+        let en_passant = before.piece_on(source) == Some(Piece::Pawn) && source.get_file() != dest.get_file();
+        match before.piece_on(dest) {
+            Some(piece) => pieces.push(piece),
+            // a pawn moving diagonally onto an empty square takes en passant
+            None if en_passant => pieces.push(Piece::Pawn),
+            None => {}
+        }
+    }
+    pieces.sort_by_key(|&piece| VALUES.iter().position(|&(p, _)| p == piece));
+    pieces
+}
+
+/// Material of White minus that of Black, in pawns.
+pub fn balance(board: &Board) -> i32 {
+    VALUES
+        .iter()
+        .map(|&(piece, value)| value * (count(board, piece, Color::White) - count(board, piece, Color::Black)))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use chess::ChessMove;
+
+    fn played(fen: Option<&str>, moves: &[&str]) -> GameHistory {
+        let mut history = fen.map_or_else(GameHistory::default, |fen| GameHistory::from_fen(fen).unwrap());
+        for uci in moves {
+            history.push(ChessMove::from_str(uci).unwrap());
+        }
+        history
+    }
+
+    #[test]
+    fn counts_captures_cheapest_first() {
+        // 1. e4 d5 2. exd5 Qxd5 3. Nc3 Qxa2 4. Rxa2
+        let history = played(None, &["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a2", "a1a2"]);
+        assert_eq!(captured(&history, Color::White), [Piece::Pawn, Piece::Pawn]);
+        assert_eq!(captured(&history, Color::Black), [Piece::Pawn, Piece::Queen]);
+        assert_eq!(balance(&history.position()), 8);
+    }
+
+    #[test]
+    fn counts_the_pawn_taken_en_passant() {
+        let history = played(None, &["e2e4", "a7a6", "e4e5", "d7d5", "e5d6"]);
+        assert_eq!(captured(&history, Color::Black), [Piece::Pawn]);
+        assert!(captured(&history, Color::White).is_empty());
+    }
+
+    #[test]
+    fn a_promotion_does_not_hide_a_captured_queen() {
+        // Black's queen is taken, then a black pawn queens
+        let fen = "3qk3/8/8/8/8/8/6p1/3QK3 w - - 0 1";
+        let history = played(Some(fen), &["d1d8", "e8d8", "e1f2", "g2g1q"]);
+        assert_eq!(captured(&history, Color::Black), [Piece::Queen]);
+        assert_eq!(captured(&history, Color::White), [Piece::Queen]);
+    }
+
+    #[test]
+    fn captures_promoted_pieces_as_what_they_became() {
+        let fen = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        let history = played(Some(fen), &["a7b8q", "e8d7", "b8b7", "d7c8", "e1e2", "c8b7"]);
+        assert_eq!(captured(&history, Color::Black), [Piece::Rook]);
+        assert_eq!(captured(&history, Color::White), [Piece::Queen]);
+    }
+
+    #[test]
+    fn set_up_positions_start_with_nothing_captured() {
+        let history = played(Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), &[]);
+        assert!(captured(&history, Color::White).is_empty());
+        assert!(captured(&history, Color::Black).is_empty());
+        assert_eq!(balance(&history.position()), 5);
+    }
+
+    #[test]
+    fn undone_captures_do_not_count() {
+        let mut history = played(None, &["e2e4", "d7d5", "e4d5"]);
+        history.undo();
+        assert!(captured(&history, Color::Black).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use chess::{Board, BoardStatus, Color, Game, GameResult, Piece, EMPTY};
+
+use crate::history::GameHistory;
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// the given side was checkmated
+    Checkmate(Color),
+    Stalemate,
+    /// the given side resigned
+    Resignation(Color),
+    /// the players agreed to a draw
+    Agreement,
+    /// the position occurred five times, or three times and a draw was claimed
+    Repetition,
+    /// 75 moves without capture or pawn move, or 50 and a draw was claimed
+    FiftyMoves,
+    /// neither side can checkmate any more
+    InsufficientMaterial,
+    /// the given side ran out of time
+    Time(Color),
+}
+
+/// The end of a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// `None` for a draw
+    pub winner: Option<Color>,
+    pub reason: Reason,
+}
+
+impl Outcome {
+    /// How `game`, whose moves are recorded in `history`, ended with its last
+    /// move or action, if it did.
+    ///
+    /// Repetitions and long stretches without progress end the game by
+    /// themselves only at the fivefold and 75-move marks, earlier only
+    /// through [`Outcome::claimed_draw`].
+    pub fn of_game(game: &Game, history: &GameHistory) -> Option<Self> {
+        let board = game.current_position();
+        let (winner, reason) = match (board.status(), game.result()) {
+            (BoardStatus::Checkmate, _) => (Some(!board.side_to_move()), Reason::Checkmate(board.side_to_move())),
+            (BoardStatus::Stalemate, _) => (None, Reason::Stalemate),
+            (_, Some(GameResult::WhiteResigns)) => (Some(Color::Black), Reason::Resignation(Color::White)),
+            (_, Some(GameResult::BlackResigns)) => (Some(Color::White), Reason::Resignation(Color::Black)),
+            (_, Some(GameResult::DrawAccepted)) => (None, Reason::Agreement),
+            _ if dead_position(&board) => (None, Reason::InsufficientMaterial),
+            _ if history.repetitions() >= 5 => (None, Reason::Repetition),
+            _ if history.halfmoves_at(history.current()) >= 150 => (None, Reason::FiftyMoves),
+            _ => return None,
+        };
+        Some(Self { winner, reason })
+    }
+
+    /// The draw claimed in the current position of `history`, if one can be.
+    ///
+    /// The claim is judged by `history` rather than `chess::Game`, which
+    /// does not know the halfmove clock a FEN start came with.
+    pub fn claimed_draw(history: &GameHistory) -> Option<Self> {
+        if !history.can_claim_draw() {
+            return None;
+        }
+        let reason = if history.repetitions() >= 3 { Reason::Repetition } else { Reason::FiftyMoves };
+        Some(Self { winner: None, reason })
+    }
+
+    /// `color` running out of time on `board`, which is a draw when the
+    /// opponent could never checkmate.
+    pub fn on_time(color: Color, board: &Board) -> Self {
+        let winner = (!insufficient_material(board, !color)).then_some(!color);
+        Self { winner, reason: Reason::Time(color) }
+    }
+
+    /// PGN result token.
+    pub fn result_token(&self) -> &'static str {
+        match self.winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    /// PGN `Termination` tag value.
+    pub fn termination(&self) -> &'static str {
+        match self.reason {
+            Reason::Time(_) => "time forfeit",
+            _ => "normal",
+        }
+    }
+
+    /// A sentence describing the result and its reason.
+    pub fn describe(&self) -> String {
+        match (self.winner, self.reason) {
+            (_, Reason::Checkmate(loser)) => format!("{} wins by checkmate", color_name(!loser)),
+            (_, Reason::Resignation(loser)) => format!("{} wins, {} resigned", color_name(!loser), color_name(loser)),
+            (Some(winner), Reason::Time(_)) => format!("{} wins on time", color_name(winner)),
+            (None, Reason::Time(loser)) => format!(
+                "Draw: {} ran out of time, but {} cannot checkmate",
+                color_name(loser),
+                color_name(!loser)
+            ),
+            (_, Reason::Stalemate) => "Draw by stalemate".to_owned(),
+            (_, Reason::Agreement) => "Draw by agreement".to_owned(),
+            (_, Reason::Repetition) => "Draw by repetition".to_owned(),
+            (_, Reason::FiftyMoves) => "Draw by the fifty-move rule".to_owned(),
+            (_, Reason::InsufficientMaterial) => "Draw by insufficient material".to_owned(),
+        }
+    }
+}
+
+/// `White` or `Black`.
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+/// Whether `color` could never checkmate, however the opponent played: a
+/// bare king, or a king with a single bishop or knight against a bare king.
+///
+/// Anything else the opponent has might block their own king's escape, so a
+/// lone minor piece may still mate it.
+pub fn insufficient_material(board: &Board, color: Color) -> bool {
+    let own = *board.color_combined(color);
+    let major = (*board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen)) & own;
+    let minor = (*board.pieces(Piece::Knight) | *board.pieces(Piece::Bishop)) & own;
+    let opponent_bare = board.color_combined(!color).popcnt() == 1;
+    major == EMPTY && (minor == EMPTY || (minor.popcnt() == 1 && opponent_bare))
+}
+
+/// Whether neither side could ever checkmate: bare kings plus at most a single
+/// bishop or knight, or one bishop each on squares of the same colour.
+pub fn dead_position(board: &Board) -> bool {
+    let major = *board.pieces(Piece::Pawn) | *board.pieces(Piece::Rook) | *board.pieces(Piece::Queen);
+    let knights = *board.pieces(Piece::Knight);
+    let bishops = *board.pieces(Piece::Bishop);
+    if major != EMPTY || knights.popcnt() + bishops.popcnt() > 2 {
+        return false;
+    }
+    match (knights.popcnt(), bishops.popcnt()) {
+        (0, 2) => {
+            let white = bishops & *board.color_combined(Color::White);
+            let shade = |square: chess::Square| (square.get_rank().to_index() + square.get_file().to_index()) % 2;
+            let mut shades = bishops.map(shade);
+            white.popcnt() == 1 && shades.next() == shades.next()
+        }
+        (knights, bishops) => knights + bishops <= 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    use chess::ChessMove;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    /// The outcome after playing `moves` from `fen`, or from the usual start.
+    fn outcome(fen: Option<&str>, moves: &[&str]) -> Option<Outcome> {
+        let mut history = fen.map_or_else(GameHistory::default, |fen| GameHistory::from_fen(fen).unwrap());
+        for uci in moves {
+            history.push(ChessMove::from_str(uci).unwrap());
+        }
+        Outcome::of_game(&history.game().0, &history)
+    }
+
+    #[test]
+    fn checkmate() {
+        let outcome = outcome(None, &["f2f3", "e7e5", "g2g4", "d8h4"]).unwrap();
+        assert_eq!(outcome, Outcome { winner: Some(Color::Black), reason: Reason::Checkmate(Color::White) });
+        assert_eq!(outcome.result_token(), "0-1");
+        assert_eq!(outcome.describe(), "Black wins by checkmate");
+    }
+
+    #[test]
+    fn stalemate() {
+        let outcome = outcome(Some("k7/8/8/1Q6/8/8/8/K7 w - - 0 1"), &["b5b6"]);
+        assert_eq!(outcome, Some(Outcome { winner: None, reason: Reason::Stalemate }));
+    }
+
+    #[test]
+    fn fivefold_repetition() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let moves: Vec<&str> = shuffle.iter().cycle().take(16).copied().collect();
+        // the starting position has occurred four times, which is only claimable
+        assert_eq!(outcome(None, &moves[..12]), None);
+        assert_eq!(outcome(None, &moves), Some(Outcome { winner: None, reason: Reason::Repetition }));
+    }
+
+    #[test]
+    fn seventy_five_move_rule() {
+        assert_eq!(outcome(Some("4k3/8/8/8/8/8/8/R3K3 w - - 148 80"), &["a1a2"]), None);
+        assert_eq!(
+            outcome(Some("4k3/8/8/8/8/8/8/R3K3 w - - 149 80"), &["a1a2"]),
+            Some(Outcome { winner: None, reason: Reason::FiftyMoves })
+        );
+        // a capture resets the count
+        assert_eq!(outcome(Some("4k3/8/8/8/8/8/r7/R3K3 w - - 149 80"), &["a1a2"]), None);
+    }
+
+    #[test]
+    fn capturing_the_last_mating_material() {
+        assert_eq!(
+            outcome(Some("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1"), &["e1d2"]),
+            Some(Outcome { winner: None, reason: Reason::InsufficientMaterial })
+        );
+    }
+
+    #[test]
+    fn dead_positions() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+            // bishops on squares of the same colour
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            assert!(dead_position(&board(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn positions_with_mating_chances_are_not_dead() {
+        for fen in [
+            // bishops on squares of opposite colours can still mate in a corner
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+        ] {
+            assert!(!dead_position(&board(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn flagging_loses_to_mating_material() {
+        // White flags, and Black could still mate
+        for fen in [
+            "4k3/4r3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/4p3/8/8/8/8/8/4K3 w - - 0 1",
+            "3nk3/4n3/8/8/8/8/8/4K3 w - - 0 1",
+            // a lone knight mates with help from the flagger's own pawns
+            "4k3/4n3/8/8/8/8/3P4/4K3 w - - 0 1",
+            "4k3/4b3/8/8/8/8/8/3NK3 w - - 0 1",
+        ] {
+            assert_eq!(Outcome::on_time(Color::White, &board(fen)).winner, Some(Color::Black), "{fen}");
+        }
+    }
+
+    #[test]
+    fn flagging_draws_when_the_opponent_cannot_mate() {
+        for fen in [
+            "4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1",
+            "4k3/4n3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/4b3/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            let outcome = Outcome::on_time(Color::White, &board(fen));
+            assert_eq!(outcome.winner, None, "{fen}");
+            assert_eq!(outcome.reason, Reason::Time(Color::White));
+        }
+    }
+}
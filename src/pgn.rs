@@ -0,0 +1,770 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use chess::{Board, ChessMove, Color, GameResult};
+
+use crate::game::MyGame;
+use crate::history::{check_kings, GameHistory};
+use crate::outcome::Outcome;
+use crate::san::{self, SanError};
+
+/// Result token for the PGN `Result` tag and the end of the movetext.
+pub fn result_token(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::WhiteCheckmates) | Some(GameResult::BlackResigns) => "1-0",
+        Some(GameResult::BlackCheckmates) | Some(GameResult::WhiteResigns) => "0-1",
+        Some(GameResult::Stalemate) | Some(GameResult::DrawAccepted) | Some(GameResult::DrawDeclared) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// Writes `game`, played from the start of `history`, in PGN export format.
+///
+/// `outcome` takes precedence over the result recorded by the game.
+pub fn to_pgn(game: &MyGame, history: &GameHistory, outcome: Option<&Outcome>) -> String {
+    let start = history.start();
+    let result = match outcome {
+        Some(outcome) => outcome.result_token(),
+        None => result_token(game.0.result()),
+    };
+
+    let mut tags = vec![
+        ("Event", "Casual game".to_owned()),
+        ("Site", "?".to_owned()),
+        ("Date", today()),
+        ("Round", "-".to_owned()),
+        ("White", "?".to_owned()),
+        ("Black", "?".to_owned()),
+        ("Result", result.to_owned()),
+    ];
+    if let Some(outcome) = outcome {
+        tags.push(("Termination", outcome.termination().to_owned()));
+    }
+    if start != Board::default() {
+        tags.push(("SetUp", "1".to_owned()));
+        tags.push(("FEN", history.fen_at(0)));
+    }
+
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut side = start.side_to_move();
+    let mut number = history.first_move_number();
+    let moves: Vec<_> = history.plies()[..history.current()].iter().map(|ply| ply.chess_move).collect();
+    for (i, san) in san::move_list(start, &moves).into_iter().enumerate() {
+        if side == Color::White {
+            tokens.push(format!("{number}."));
+        } else if i == 0 {
+            tokens.push(format!("{number}..."));
+        }
+        tokens.push(san);
+        if side == Color::Black {
+            number += 1;
+        }
+        side = !side;
+    }
+    tokens.push(result.to_owned());
+
+    // export format keeps movetext lines within 80 columns
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push_str("\n\n");
+    pgn
+}
+
+/// Saves `game` as a PGN file at `path`.
+pub fn save(game: &MyGame, history: &GameHistory, outcome: Option<&Outcome>, path: &Path) -> Result<()> {
+    fs::write(path, to_pgn(game, history, outcome))
+        .with_context(|| format!("failed to write PGN to {}", path.display()))
+}
+
+/// File name for a game saved without an explicit path.
+pub fn default_file_name() -> String {
+    let (date, time) = now();
+    format!("tchess-{}-{}.pgn", date.replace('.', ""), time)
+}
+
+/// Today's date in the `YYYY.MM.DD` form of the `Date` tag.
+fn today() -> String {
+    now().0
+}
+
+/// Current UTC date as `YYYY.MM.DD` and time as `HHMMSS`.
+fn now() -> (String, String) {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{year:04}.{month:02}.{day:02}"),
+        format!("{:02}{:02}{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+    )
+}
+
+/// A game read from a PGN file.
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    /// tag pairs in file order
+    pub tags: Vec<(String, String)>,
+    /// position given by the `FEN` tag, or the standard start
+    pub start: Board,
+    /// comment before the first move
+    pub comment: Option<String>,
+    /// main line
+    pub moves: Vec<PgnMove>,
+    /// game termination marker
+    pub result: String,
+}
+
+impl PgnGame {
+    /// The moves of `history` up to its current ply, as a game ended by `outcome`.
+    pub fn from_history(history: &GameHistory, outcome: Option<&Outcome>) -> Self {
+        let result = outcome.map_or("*", |outcome| outcome.result_token()).to_owned();
+        let mut moves: Vec<PgnMove> = history.plies()[..history.current()]
+            .iter()
+            .enumerate()
+            .map(|(ply, p)| PgnMove {
+                chess_move: p.chess_move,
+                san: history.sans()[ply].clone(),
+                nags: Vec::new(),
+                comment: None,
+                variations: Vec::new(),
+            })
+            .collect();
+        let comment = outcome.map(Outcome::describe);
+        let game_comment = match moves.last_mut() {
+            Some(last) => {
+                last.comment = comment;
+                None
+            }
+            None => comment,
+        };
+        Self {
+            tags: vec![
+                ("White".to_owned(), "White".to_owned()),
+                ("Black".to_owned(), "Black".to_owned()),
+                ("Result".to_owned(), result.clone()),
+                ("FEN".to_owned(), history.fen_at(0)),
+            ],
+            start: history.start(),
+            comment: game_comment,
+            moves,
+            result,
+        }
+    }
+
+    /// Value of the tag called `name`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// A move of a PGN game, with the annotations that follow it.
+#[derive(Debug, Clone)]
+pub struct PgnMove {
+    pub chess_move: ChessMove,
+    /// the move as written in the file
+    pub san: String,
+    /// numeric annotation glyphs, with `!`, `?` and friends converted
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    /// alternatives to this move, when variations are kept
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+/// Error in a PGN file, at a 1-based line and column.
+#[derive(Debug, Clone)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// Reads every game in the PGN file at `path`.
+pub fn load(path: &Path, keep_variations: bool) -> Result<Vec<PgnGame>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&text, keep_variations).with_context(|| format!("invalid PGN in {}", path.display()))
+}
+
+/// Reads every game in `text`.
+///
+/// Variations are checked and attached to the move they replace when
+/// `keep_variations` is set, and skipped unread otherwise.
+pub fn parse(text: &str, keep_variations: bool) -> Result<Vec<PgnGame>, PgnError> {
+    let mut parser = Parser { tokens: tokenize(text)?, next: 0, keep_variations };
+    let mut games = Vec::new();
+    while parser.peek().is_some() {
+        let game = parser.game()?;
+        // a comment after the last game, or between games, is no game of its own
+        if !game.tags.is_empty() || !game.moves.is_empty() {
+            games.push(game);
+        }
+    }
+    Ok(games)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    TagOpen,
+    TagClose,
+    String(String),
+    Symbol(String),
+    Nag(u8),
+    Comment(String),
+    VariationOpen,
+    VariationClose,
+    Period,
+    Asterisk,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> PgnError {
+        PgnError { line: self.line, column: self.column, message: message.into() }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    // advances past one character, keeping the position up to date
+    macro_rules! bump {
+        () => {{
+            let c = chars.next();
+            if c == Some('\n') {
+                line += 1;
+                column = 1;
+            } else if c.is_some() {
+                column += 1;
+            }
+            c
+        }};
+    }
+
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_column) = (line, column);
+        let error = |message: &str| PgnError { line: start_line, column: start_column, message: message.to_owned() };
+        let kind = match c {
+            c if c.is_whitespace() => {
+                bump!();
+                continue;
+            }
+            // escape lines are ignored entirely
+            '%' if column == 1 => {
+                while !matches!(bump!(), Some('\n') | None) {}
+                continue;
+            }
+            ';' => {
+                bump!();
+                let mut comment = String::new();
+                while let Some(c) = bump!().filter(|c| *c != '\n') {
+                    comment.push(c);
+                }
+                TokenKind::Comment(comment.trim().to_owned())
+            }
+            '{' => {
+                bump!();
+                let mut comment = String::new();
+                loop {
+                    match bump!() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(error("unterminated comment")),
+                    }
+                }
+                TokenKind::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            '"' => {
+                bump!();
+                let mut value = String::new();
+                loop {
+                    match bump!() {
+                        Some('"') => break,
+                        Some('\\') => match bump!() {
+                            Some(c) => value.push(c),
+                            None => return Err(error("unterminated string")),
+                        },
+                        Some('\n') | None => return Err(error("unterminated string")),
+                        Some(c) => value.push(c),
+                    }
+                }
+                TokenKind::String(value)
+            }
+            '$' => {
+                bump!();
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    bump!();
+                }
+                TokenKind::Nag(digits.parse().map_err(|_| error("invalid annotation glyph"))?)
+            }
+            '!' | '?' => {
+                let mut glyph = String::new();
+                while let Some(&c) = chars.peek().filter(|c| matches!(c, '!' | '?')) {
+                    glyph.push(c);
+                    bump!();
+                }
+                TokenKind::Nag(match glyph.as_str() {
+                    "!" => 1,
+                    "?" => 2,
+                    "!!" => 3,
+                    "??" => 4,
+                    "!?" => 5,
+                    "?!" => 6,
+                    _ => return Err(error(&format!("unknown annotation `{glyph}`"))),
+                })
+            }
+            '[' | ']' | '(' | ')' | '.' | '*' => {
+                bump!();
+                match c {
+                    '[' => TokenKind::TagOpen,
+                    ']' => TokenKind::TagClose,
+                    '(' => TokenKind::VariationOpen,
+                    ')' => TokenKind::VariationClose,
+                    '.' => TokenKind::Period,
+                    _ => TokenKind::Asterisk,
+                }
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let mut symbol = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(**c)) {
+                    symbol.push(c);
+                    bump!();
+                }
+                TokenKind::Symbol(symbol)
+            }
+            c => return Err(error(&format!("unexpected character `{c}`"))),
+        };
+        tokens.push(Token { kind, line: start_line, column: start_column });
+    }
+    Ok(tokens)
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    keep_variations: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /// Error at the end of the file.
+    fn eof(&self, message: &str) -> PgnError {
+        let (line, column) = self.tokens.last().map_or((1, 1), |t| (t.line, t.column));
+        PgnError { line, column, message: message.to_owned() }
+    }
+
+    fn game(&mut self) -> Result<PgnGame, PgnError> {
+        let mut tags = Vec::new();
+        let mut start = Board::default();
+        while self.peek().map(|t| &t.kind) == Some(&TokenKind::TagOpen) {
+            self.advance();
+            let name = match self.advance() {
+                Some(Token { kind: TokenKind::Symbol(name), .. }) => name,
+                Some(token) => return Err(token.error("expected a tag name")),
+                None => return Err(self.eof("unterminated tag")),
+            };
+            let value = match self.advance() {
+                Some(Token { kind: TokenKind::String(value), .. }) => value,
+                Some(token) => return Err(token.error(format!("expected a value for tag `{name}`"))),
+                None => return Err(self.eof("unterminated tag")),
+            };
+            match self.advance() {
+                Some(Token { kind: TokenKind::TagClose, .. }) => {}
+                Some(token) => return Err(token.error("expected `]`")),
+                None => return Err(self.eof("unterminated tag")),
+            }
+            if name == "FEN" {
+                let token = &self.tokens[self.next - 2];
+                check_kings(&value).map_err(|e| token.error(format!("invalid FEN `{value}`: {e}")))?;
+                start = Board::from_str(&value)
+                    .map_err(|_| token.error(format!("invalid FEN `{value}`")))?;
+            }
+            tags.push((name, value));
+        }
+
+        let mut game = PgnGame { tags, start, comment: None, moves: Vec::new(), result: "*".to_owned() };
+        let mut comment = None;
+        let (moves, result) = self.line(start, &mut comment, None)?;
+        game.moves = moves;
+        game.comment = comment;
+        if let Some(result) = result {
+            game.result = result;
+        } else if let Some(result) = game.tag("Result") {
+            game.result = result.to_owned();
+        }
+        Ok(game)
+    }
+
+    /// Reads moves played from `board` until the end of the game, or of the
+    /// variation opened by `open`.
+    ///
+    /// Returns the moves and, for the main line, the result token.
+    fn line(
+        &mut self,
+        mut board: Board,
+        comment: &mut Option<String>,
+        open: Option<&Token>,
+    ) -> Result<(Vec<PgnMove>, Option<String>), PgnError> {
+        let main_line = open.is_none();
+        let mut moves: Vec<PgnMove> = Vec::new();
+        // position before the last move, where its variations branch off
+        let mut before = board;
+
+        while let Some(token) = self.peek().cloned() {
+            match &token.kind {
+                TokenKind::TagOpen if main_line => return Ok((moves, None)),
+                TokenKind::VariationClose if !main_line => {
+                    self.advance();
+                    return Ok((moves, None));
+                }
+                TokenKind::Asterisk if main_line => {
+                    self.advance();
+                    return Ok((moves, Some("*".to_owned())));
+                }
+                TokenKind::Symbol(symbol) if main_line && RESULTS.contains(&symbol.as_str()) => {
+                    self.advance();
+                    return Ok((moves, Some(symbol.clone())));
+                }
+                TokenKind::Symbol(symbol) if symbol.chars().all(|c| c.is_ascii_digit()) => {
+                    self.advance();
+                }
+                TokenKind::Symbol(symbol) => {
+                    self.advance();
+                    let chess_move = san::from_san(&board, symbol).map_err(|e| match e {
+                        SanError::Ambiguous(ref moves) => token.error(format!(
+                            "{e} `{symbol}`, could be {}",
+                            moves.join(" or ")
+                        )),
+                        e => token.error(format!("{e} `{symbol}`")),
+                    })?;
+                    before = board;
+                    board = board.make_move_new(chess_move);
+                    moves.push(PgnMove {
+                        chess_move,
+                        san: symbol.clone(),
+                        nags: Vec::new(),
+                        comment: None,
+                        variations: Vec::new(),
+                    });
+                }
+                TokenKind::Period => {
+                    self.advance();
+                }
+                TokenKind::Nag(nag) => {
+                    self.advance();
+                    match moves.last_mut() {
+                        Some(last) => last.nags.push(*nag),
+                        None => return Err(token.error("annotation before any move")),
+                    }
+                }
+                TokenKind::Comment(text) => {
+                    self.advance();
+                    let target = match moves.last_mut() {
+                        Some(last) => &mut last.comment,
+                        None => &mut *comment,
+                    };
+                    match target {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(text);
+                        }
+                        None => *target = Some(text.clone()),
+                    }
+                }
+                TokenKind::VariationOpen => {
+                    self.advance();
+                    if moves.is_empty() {
+                        return Err(token.error("variation before any move"));
+                    }
+                    if self.keep_variations {
+                        let (variation, _) = self.line(before, &mut None, Some(&token))?;
+                        moves.last_mut().unwrap().variations.push(variation);
+                    } else {
+                        self.skip_variation(&token)?;
+                    }
+                }
+                _ => return Err(token.error("unexpected token")),
+            }
+        }
+
+        match open {
+            Some(open) => Err(open.error("unterminated variation")),
+            None => Ok((moves, None)),
+        }
+    }
+
+    /// Skips to the end of the variation opened by `open`.
+    fn skip_variation(&mut self, open: &Token) -> Result<(), PgnError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance().map(|t| t.kind) {
+                Some(TokenKind::VariationOpen) => depth += 1,
+                Some(TokenKind::VariationClose) => depth -= 1,
+                Some(_) => {}
+                None => return Err(open.error("unterminated variation")),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::outcome::Reason;
+
+    fn error(text: &str) -> PgnError {
+        parse(text, true).expect_err("the PGN should not parse")
+    }
+
+    fn played(fen: Option<&str>, moves: &[&str]) -> GameHistory {
+        let mut history = fen.map_or_else(GameHistory::default, |fen| GameHistory::from_fen(fen).unwrap());
+        for uci in moves {
+            history.push(ChessMove::from_str(uci).unwrap());
+        }
+        history
+    }
+
+    fn export(history: &GameHistory, outcome: Option<&Outcome>) -> String {
+        to_pgn(&history.game(), history, outcome)
+    }
+
+    fn tag_names(pgn: &str) -> Vec<&str> {
+        pgn.lines()
+            .take_while(|line| line.starts_with('['))
+            .map(|line| line[1..].split(' ').next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn exports_the_seven_tag_roster_first() {
+        let pgn = export(&played(None, &["e2e4"]), None);
+        assert_eq!(tag_names(&pgn), ["Event", "Site", "Date", "Round", "White", "Black", "Result"]);
+        let date = pgn.lines().nth(2).unwrap();
+        assert_eq!(date.len(), "[Date \"2024.01.01\"]".len(), "{date}");
+        assert!(pgn.ends_with("1. e4 *\n\n"), "{pgn}");
+
+        let outcome = Outcome { winner: Some(Color::White), reason: Reason::Time(Color::Black) };
+        let pgn = export(&played(None, &["e2e4"]), Some(&outcome));
+        assert_eq!(tag_names(&pgn)[7..], ["Termination"]);
+        assert!(pgn.contains("[Termination \"time forfeit\"]"), "{pgn}");
+    }
+
+    #[test]
+    fn exports_the_result_of_each_outcome() {
+        let history = played(None, &["e2e4", "e7e5"]);
+        for (winner, reason, token) in [
+            (Some(Color::White), Reason::Checkmate(Color::Black), "1-0"),
+            (Some(Color::Black), Reason::Checkmate(Color::White), "0-1"),
+            (Some(Color::Black), Reason::Resignation(Color::White), "0-1"),
+            (Some(Color::White), Reason::Time(Color::Black), "1-0"),
+            (None, Reason::Time(Color::Black), "1/2-1/2"),
+            (None, Reason::Stalemate, "1/2-1/2"),
+            (None, Reason::Agreement, "1/2-1/2"),
+            (None, Reason::Repetition, "1/2-1/2"),
+            (None, Reason::FiftyMoves, "1/2-1/2"),
+            (None, Reason::InsufficientMaterial, "1/2-1/2"),
+        ] {
+            let pgn = export(&history, Some(&Outcome { winner, reason }));
+            assert!(pgn.contains(&format!("[Result \"{token}\"]")), "{reason:?}: {pgn}");
+            assert!(pgn.ends_with(&format!(" {token}\n\n")), "{reason:?}: {pgn}");
+        }
+
+        // without an outcome the game's own result is used
+        let pgn = export(&played(None, &["f2f3", "e7e5", "g2g4", "d8h4"]), None);
+        assert!(pgn.ends_with("2. g4 Qh4# 0-1\n\n"), "{pgn}");
+        assert!(export(&history, None).ends_with(" *\n\n"));
+    }
+
+    #[test]
+    fn exports_set_up_positions() {
+        assert!(!export(&played(None, &[]), None).contains("[SetUp"));
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 3 12";
+        let pgn = export(&played(Some(fen), &["e8d7", "e2e4"]), None);
+        assert_eq!(tag_names(&pgn)[7..], ["SetUp", "FEN"]);
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 3 12\"]\n"), "{pgn}");
+        assert!(pgn.ends_with("\n12... Kd7 13. e4 *\n\n"), "{pgn}");
+    }
+
+    #[test]
+    fn wraps_movetext_under_80_columns() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8", "b1c3", "b8c6", "c3b1", "c6b8"];
+        let moves: Vec<&str> = shuffle.iter().cycle().take(200).copied().collect();
+        let pgn = export(&played(None, &moves), None);
+        let movetext: Vec<&str> = pgn.lines().skip_while(|line| !line.is_empty()).skip(1).collect();
+        assert!(movetext.len() > 10);
+        for line in movetext {
+            assert!(line.len() < 80, "{line}");
+            assert!(!line.starts_with(' ') && !line.ends_with(' '), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn exported_games_read_back() {
+        for (fen, moves) in [
+            (None, &["e2e4", "d7d5", "e4d5", "g8f6", "f1b5", "c7c6", "d5c6", "d8d2", "b1d2", "b7c6"][..]),
+            (Some("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 30"), &["e1c1", "e8g8", "b7b8q", "a8b8"][..]),
+        ] {
+            let history = played(fen, moves);
+            let outcome = Outcome { winner: None, reason: Reason::Agreement };
+            let games = parse(&export(&history, Some(&outcome)), false).unwrap();
+            assert_eq!(games.len(), 1);
+            let game = &games[0];
+            assert_eq!(game.start, history.start());
+            assert_eq!(game.result, "1/2-1/2");
+            assert_eq!(game.tag("Result"), Some("1/2-1/2"));
+            let read: Vec<_> = game.moves.iter().map(|m| m.chess_move).collect();
+            let written: Vec<_> = history.plies().iter().map(|ply| ply.chess_move).collect();
+            assert_eq!(read, written);
+            assert_eq!(game.moves.iter().map(|m| m.san.as_str()).collect::<Vec<_>>(), history.sans());
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let e = error("1. e4 {never closed");
+        assert_eq!((e.line, e.column, e.message.as_str()), (1, 7, "unterminated comment"));
+
+        let e = error("1. e4 e5\n2. Nf3 & *");
+        assert_eq!((e.line, e.column), (2, 8));
+        assert_eq!(e.message, "unexpected character `&`");
+
+        let e = error("[Event \"x\"]\n\n1. e4 e5 2. Ke3 *");
+        assert_eq!((e.line, e.column), (3, 13));
+        assert_eq!(e.message, "illegal move `Ke3`");
+
+        let e = error("1. Nf3 a6 2. d3 a5 3. Nd2 *");
+        assert_eq!((e.line, e.column), (1, 23));
+        assert_eq!(e.message, "ambiguous move `Nd2`, could be Nbd2 or Nfd2");
+
+        let e = error("1. e4 (1. d4 d5");
+        assert_eq!((e.line, e.column, e.message.as_str()), (1, 7, "unterminated variation"));
+
+        let e = error("[Event \"x\"\n1. e4 *");
+        assert_eq!((e.line, e.column, e.message.as_str()), (2, 1, "expected `]`"));
+
+        let e = error("1. e4 $ *");
+        assert_eq!((e.line, e.column, e.message.as_str()), (1, 7, "invalid annotation glyph"));
+    }
+
+    #[test]
+    fn fen_tags_need_both_kings() {
+        let e = error("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n*");
+        assert_eq!((e.line, e.column), (1, 6));
+        assert!(e.message.starts_with("invalid FEN"), "{}", e.message);
+
+        let e = error("[FEN \"4k3/8/8/8/8/8/8/8 w - - 0 1\"]\n\n*");
+        assert_eq!((e.line, e.column), (1, 6));
+    }
+
+    #[test]
+    fn fen_tags_set_the_start() {
+        let games = parse("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n\n1. e4 Kd7 *", false).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].start, Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap());
+        assert_eq!(games[0].moves.len(), 2);
+    }
+
+    #[test]
+    fn reads_every_game_of_a_file() {
+        let text = "[Event \"first\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n\n\
+                    [Event \"second\"]\n\n1. d4 d5 1/2-1/2\n";
+        let games = parse(text, false).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("first"));
+        assert_eq!(games[0].moves.len(), 7);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].tag("Event"), Some("second"));
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].result, "1/2-1/2");
+    }
+
+    #[test]
+    fn comments_between_games_are_no_games() {
+        let text = "[Event \"first\"]\n\n1. e4 * {between}\n\n[Event \"second\"]\n\n1. d4 *\n{after the last game}\n; and a rest-of-line one\n";
+        let games = parse(text, false).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("first"));
+        assert_eq!(games[1].tag("Event"), Some("second"));
+        assert!(parse("{only a comment}", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_comments_annotations_and_variations() {
+        let text = "{opening} 1. e4 $1 {king's pawn} (1. d4 d5) e5?! *";
+        let games = parse(text, true).unwrap();
+        let game = &games[0];
+        assert_eq!(game.comment.as_deref(), Some("opening"));
+        assert_eq!(game.moves[0].nags, vec![1]);
+        assert_eq!(game.moves[0].comment.as_deref(), Some("king's pawn"));
+        assert_eq!(game.moves[0].variations.len(), 1);
+        assert_eq!(game.moves[0].variations[0].len(), 2);
+        assert_eq!(game.moves[1].nags, vec![6]);
+
+        let games = parse(text, false).unwrap();
+        assert!(games[0].moves[0].variations.is_empty());
+        assert_eq!(games[0].moves.len(), 2);
+    }
+}
@@ -0,0 +1,387 @@
+use std::fmt;
+
+use chess::{Board, BoardStatus, ChessMove, Color, File, MoveGen, Piece, Rank, EMPTY};
+
+/// Letter used for `piece` in SAN, empty for pawns.
+pub fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn   => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook   => "R",
+        Piece::Queen  => "Q",
+        Piece::King   => "K",
+    }
+}
+
+/// Writes `chess_move`, which must be legal on `board`, in Standard Algebraic Notation.
+pub fn to_san(board: &Board, chess_move: ChessMove) -> String {
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
+    let piece = board.piece_on(source).expect("SAN of a move without a piece");
+
+    let mut san = String::new();
+    if piece == Piece::King && source.get_file().to_index().abs_diff(dest.get_file().to_index()) == 2 {
+        san.push_str(if dest.get_file() > source.get_file() { "O-O" } else { "O-O-O" });
+    } else {
+        // pawns capture diagonally even when taking en passant onto an empty square
+        let capture = board.piece_on(dest).is_some()
+            || (piece == Piece::Pawn && source.get_file() != dest.get_file());
+
+        san.push_str(piece_letter(piece));
+        if piece == Piece::Pawn {
+            if capture {
+                san.push_str(&source.to_string()[..1]);
+            }
+        } else {
+            san.push_str(&disambiguation(board, chess_move, piece));
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = chess_move.get_promotion() {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+    }
+
+    let after = board.make_move_new(chess_move);
+    if after.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if *after.checkers() != EMPTY {
+        san.push('+');
+    }
+    san
+}
+
+/// File, rank or both of the source square, when another piece of the same kind
+/// could also reach the destination.
+fn disambiguation(board: &Board, chess_move: ChessMove, piece: Piece) -> String {
+    let source = chess_move.get_source();
+    let rivals: Vec<_> = MoveGen::new_legal(board)
+        .filter(|m| {
+            m.get_dest() == chess_move.get_dest()
+                && m.get_source() != source
+                && board.piece_on(m.get_source()) == Some(piece)
+        })
+        .map(|m| m.get_source())
+        .collect();
+
+    let square = source.to_string();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|s| s.get_file() != source.get_file()) {
+        square[..1].to_owned()
+    } else if rivals.iter().all(|s| s.get_rank() != source.get_rank()) {
+        square[1..].to_owned()
+    } else {
+        square
+    }
+}
+
+/// SAN of every move in `moves`, replayed from `start`.
+pub fn move_list(start: Board, moves: &[ChessMove]) -> Vec<String> {
+    let mut board = start;
+    let mut list = Vec::new();
+    for &chess_move in moves {
+        list.push(to_san(&board, chess_move));
+        board = board.make_move_new(chess_move);
+    }
+    list
+}
+
+/// Groups `sans` into numbered pairs, starting from move `number` with `first_mover` to play.
+///
+/// A game starting with Black to move opens with `1... `.
+pub fn numbered_pairs(sans: &[String], first_mover: Color, number: u32) -> Vec<String> {
+    let mut pairs = Vec::new();
+    let mut moves = sans.iter();
+    if first_mover == Color::Black {
+        if let Some(black) = moves.next() {
+            pairs.push(format!("{number}... {black}"));
+        }
+    }
+    let first_number = number + pairs.len() as u32;
+    let rest: Vec<_> = moves.collect();
+    for (number, pair) in (first_number..).zip(rest.chunks(2)) {
+        match pair {
+            [white, black] => pairs.push(format!("{number}. {white} {black}")),
+            [white] => pairs.push(format!("{number}. {white}")),
+            _ => unreachable!(),
+        }
+    }
+    pairs
+}
+
+/// Why a SAN move could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    /// not SAN at all
+    Malformed,
+    /// no legal move matches
+    Illegal,
+    /// several legal moves match, written out in full
+    Ambiguous(Vec<String>),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Malformed => write!(f, "unreadable move"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous(_) => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+/// Reads a SAN move, checking it against the legal moves on `board`.
+///
+/// Annotation suffixes, `0-0` castling and a missing `=` before the promotion
+/// piece are accepted.
+pub fn from_san(board: &Board, text: &str) -> Result<ChessMove, SanError> {
+    let text = text.trim_end_matches(['+', '#', '!', '?']);
+    let castle = match text {
+        "O-O" | "0-0" => Some(File::G),
+        "O-O-O" | "0-0-0" => Some(File::C),
+        _ => None,
+    };
+
+    let candidates: Vec<ChessMove> = match castle {
+        Some(file) => {
+            let king = board.king_square(board.side_to_move());
+            MoveGen::new_legal(board)
+                .filter(|m| {
+                    m.get_source() == king
+                        && m.get_source().get_file() == File::E
+                        && m.get_dest().get_file() == file
+                })
+                .collect()
+        }
+        None => {
+            let spec = SanSpec::parse(text).ok_or(SanError::Malformed)?;
+            MoveGen::new_legal(board).filter(|m| spec.matches(board, *m)).collect()
+        }
+    };
+
+    match candidates[..] {
+        [chess_move] => Ok(chess_move),
+        [] => Err(SanError::Illegal),
+        _ => Err(SanError::Ambiguous(
+            candidates.iter().map(|m| to_san(board, *m)).collect(),
+        )),
+    }
+}
+
+/// The parts of a non-castling SAN move.
+struct SanSpec {
+    piece: Piece,
+    file: Option<File>,
+    rank: Option<Rank>,
+    dest: chess::Square,
+    promotion: Option<Piece>,
+}
+
+impl SanSpec {
+    fn parse(text: &str) -> Option<Self> {
+        let mut chars: Vec<char> = text.chars().collect();
+
+        let piece = match chars.first()? {
+            'N' => Piece::Knight,
+            'B' => Piece::Bishop,
+            'R' => Piece::Rook,
+            'Q' => Piece::Queen,
+            'K' => Piece::King,
+            _ => Piece::Pawn,
+        };
+        if piece != Piece::Pawn {
+            chars.remove(0);
+        }
+
+        let promotion = match chars.last()? {
+            'N' => Some(Piece::Knight),
+            'B' => Some(Piece::Bishop),
+            'R' => Some(Piece::Rook),
+            'Q' => Some(Piece::Queen),
+            _ => None,
+        };
+        if promotion.is_some() {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        let rank = chars.pop().and_then(|c| c.to_digit(10)).filter(|r| (1..=8).contains(r))?;
+        let file = chars.pop().filter(|c| ('a'..='h').contains(c))?;
+        let dest = chess::Square::make_square(
+            Rank::from_index(rank as usize - 1),
+            File::from_index(file as usize - 'a' as usize),
+        );
+
+        // what is left is the source hint, the capture mark or a long-algebraic dash
+        chars.retain(|c| *c != 'x' && *c != '-');
+        let mut hint = chars.into_iter().peekable();
+        let file = hint.next_if(|c| ('a'..='h').contains(c)).map(|c| File::from_index(c as usize - 'a' as usize));
+        let rank = hint.next_if(|c| ('1'..='8').contains(c)).map(|c| Rank::from_index(c as usize - '1' as usize));
+        if hint.next().is_some() {
+            return None;
+        }
+
+        Some(Self { piece, file, rank, dest, promotion })
+    }
+
+    fn matches(&self, board: &Board, chess_move: ChessMove) -> bool {
+        let source = chess_move.get_source();
+        board.piece_on(source) == Some(self.piece)
+            && chess_move.get_dest() == self.dest
+            && chess_move.get_promotion() == self.promotion
+            && self.file.is_none_or(|file| source.get_file() == file)
+            && self.rank.is_none_or(|rank| source.get_rank() == rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chess::Square;
+
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::from_str(fen).unwrap()
+    }
+
+    fn read(fen: &str, san: &str) -> Result<String, SanError> {
+        from_san(&board(fen), san).map(|chess_move| chess_move.to_string())
+    }
+
+    /// Rooks on a1 and h1 reach d1, rooks on a1 and a5 reach a3.
+    const ROOKS: &str = "4k3/8/8/R7/8/8/4K3/R6R w - - 0 1";
+    /// Queens on a1, c1 and a3 all reach b2.
+    const QUEENS: &str = "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1";
+
+    fn write(fen: &str, uci: &str) -> String {
+        to_san(&board(fen), ChessMove::from_str(uci).unwrap())
+    }
+
+    #[test]
+    fn to_san_disambiguates() {
+        assert_eq!(write(ROOKS, "a1d1"), "Rad1");
+        assert_eq!(write(ROOKS, "h1d1"), "Rhd1");
+        assert_eq!(write(ROOKS, "a1a3"), "R1a3");
+        assert_eq!(write(ROOKS, "a5a3"), "R5a3");
+        assert_eq!(write(QUEENS, "a1b2"), "Qa1b2");
+        assert_eq!(write(QUEENS, "c1b2"), "Qcb2");
+        assert_eq!(write(QUEENS, "a3b2"), "Q3b2");
+        // only pieces of the same kind need telling apart
+        assert_eq!(write(ROOKS, "e2d1"), "Kd1");
+        assert_eq!(write(&Board::default().to_string(), "g1f3"), "Nf3");
+    }
+
+    #[test]
+    fn to_san_castles() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(write(fen, "e1g1"), "O-O");
+        assert_eq!(write(fen, "e1c1"), "O-O-O");
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(write(fen, "e8g8"), "O-O");
+        assert_eq!(write(fen, "e8c8"), "O-O-O");
+    }
+
+    #[test]
+    fn to_san_promotes_and_captures() {
+        let fen = "1n5k/P7/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(write(fen, "a7a8q"), "a8=Q");
+        assert_eq!(write(fen, "a7a8n"), "a8=N");
+        assert_eq!(write(fen, "a7b8r"), "axb8=R+");
+        assert_eq!(write("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+        assert_eq!(write("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+        assert_eq!(write("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), "Rxd5");
+    }
+
+    #[test]
+    fn to_san_marks_check_and_mate() {
+        assert_eq!(write("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(write("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8#");
+        // a promotion can mate too
+        assert_eq!(write("7k/P5pp/8/8/8/8/8/K7 w - - 0 1", "a7a8q"), "a8=Q#");
+    }
+
+    #[test]
+    fn move_list_numbers_the_moves() {
+        let moves: Vec<_> = ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"]
+            .iter()
+            .map(|uci| ChessMove::from_str(uci).unwrap())
+            .collect();
+        let sans = move_list(Board::default(), &moves);
+        assert_eq!(sans, ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]);
+        assert_eq!(
+            numbered_pairs(&sans, Color::White, 1),
+            ["1. e4 e5", "2. Qh5 Nc6", "3. Bc4 Nf6", "4. Qxf7#"]
+        );
+        assert_eq!(numbered_pairs(&sans[1..4], Color::Black, 12), ["12... e5", "13. Qh5 Nc6"]);
+        assert!(numbered_pairs(&[], Color::Black, 1).is_empty());
+    }
+
+    #[test]
+    fn from_san_disambiguates() {
+        assert_eq!(read(ROOKS, "Rad1").as_deref(), Ok("a1d1"));
+        assert_eq!(read(ROOKS, "Rhd1").as_deref(), Ok("h1d1"));
+        assert_eq!(read(ROOKS, "R1a3").as_deref(), Ok("a1a3"));
+        assert_eq!(read(ROOKS, "R5a3").as_deref(), Ok("a5a3"));
+        assert_eq!(read(QUEENS, "Qa1b2").as_deref(), Ok("a1b2"));
+        assert_eq!(read(QUEENS, "Qc1b2").as_deref(), Ok("c1b2"));
+        assert_eq!(read(QUEENS, "Q3b2").as_deref(), Ok("a3b2"));
+        assert_eq!(read(ROOKS, "Rd1"), Err(SanError::Ambiguous(vec!["Rad1".to_owned(), "Rhd1".to_owned()])));
+        assert!(matches!(read(QUEENS, "Qab2"), Err(SanError::Ambiguous(_))));
+    }
+
+    #[test]
+    fn from_san_castles() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(read(fen, "O-O").as_deref(), Ok("e1g1"));
+        assert_eq!(read(fen, "O-O-O").as_deref(), Ok("e1c1"));
+        assert_eq!(read(fen, "0-0").as_deref(), Ok("e1g1"));
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(read(fen, "O-O").as_deref(), Ok("e8g8"));
+        assert_eq!(read(fen, "0-0-0").as_deref(), Ok("e8c8"));
+        // without the rights there is nothing to castle with
+        assert_eq!(read("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1", "O-O"), Err(SanError::Illegal));
+    }
+
+    #[test]
+    fn from_san_promotes() {
+        let fen = "1n5k/P7/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(read(fen, "a8=Q").as_deref(), Ok("a7a8q"));
+        assert_eq!(read(fen, "a8N").as_deref(), Ok("a7a8n"));
+        assert_eq!(read(fen, "axb8=R").as_deref(), Ok("a7b8r"));
+        assert_eq!(read(fen, "a8"), Err(SanError::Illegal));
+    }
+
+    #[test]
+    fn from_san_takes_en_passant() {
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(read(fen, "exd6").as_deref(), Ok("e5d6"));
+    }
+
+    #[test]
+    fn from_san_ignores_suffixes() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(read(fen, "Ra8+").as_deref(), Ok("a1a8"));
+        assert_eq!(read(fen, "Ra8#!?").as_deref(), Ok("a1a8"));
+        assert_eq!(read(&Board::default().to_string(), "e4!").as_deref(), Ok("e2e4"));
+    }
+
+    #[test]
+    fn from_san_rejects_bad_moves() {
+        let start = Board::default().to_string();
+        assert_eq!(read(&start, "e5"), Err(SanError::Illegal));
+        assert_eq!(read(&start, "Zz9"), Err(SanError::Malformed));
+        assert_eq!(read(&start, "Nf"), Err(SanError::Malformed));
+        assert_eq!(read(&start, ""), Err(SanError::Malformed));
+        assert_eq!(read(&start, "Ng1f3").as_deref(), Ok("g1f3"));
+        assert_eq!(from_san(&board(&start), "e2-e4").map(|m| m.get_dest()), Ok(Square::E4));
+    }
+}
@@ -0,0 +1,356 @@
+//! Colour themes, built in or read from TOML files.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use ratatui::style::Color;
+use serde::{Deserialize, Deserializer};
+
+use crate::config::config_dir;
+
+/// Themes shipped with tchess, by file name.
+const BUILT_IN: [(&str, &str); 5] = [
+    ("classic", include_str!("../themes/classic.toml")),
+    ("green", include_str!("../themes/green.toml")),
+    ("blue", include_str!("../themes/blue.toml")),
+    ("high-contrast", include_str!("../themes/high-contrast.toml")),
+    ("colorblind", include_str!("../themes/colorblind.toml")),
+];
+
+/// Colours of the board, the pieces and the panels around them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    #[serde(deserialize_with = "color")]
+    pub light_square: Color,
+    #[serde(deserialize_with = "color")]
+    pub dark_square: Color,
+    #[serde(deserialize_with = "color")]
+    pub white_piece: Color,
+    #[serde(deserialize_with = "color")]
+    pub black_piece: Color,
+    /// background of the selected square
+    #[serde(deserialize_with = "color")]
+    pub selected: Color,
+    /// dots on the squares the selected piece can move to, and the cursor
+    #[serde(deserialize_with = "color")]
+    pub legal_move: Color,
+    /// background of the squares the last move went from and to
+    #[serde(deserialize_with = "color")]
+    pub last_move: Color,
+    /// background of a king in check
+    #[serde(deserialize_with = "color")]
+    pub check: Color,
+    /// panel borders
+    #[serde(deserialize_with = "color")]
+    pub border: Color,
+    /// panel titles
+    #[serde(deserialize_with = "color")]
+    pub title: Color,
+    /// clocks running out and actions that need confirming
+    #[serde(deserialize_with = "color")]
+    pub warning: Color,
+    /// actions that cannot be taken yet
+    #[serde(deserialize_with = "color")]
+    pub disabled: Color,
+}
+
+impl Default for Palette {
+    /// The classic theme, which themes leaving out a colour take it from.
+    fn default() -> Self {
+        Self {
+            light_square: Color::Rgb(0xc0, 0xc1, 0xc3),
+            dark_square: Color::Rgb(0xb5, 0x87, 0x63),
+            white_piece: Color::White,
+            black_piece: Color::Black,
+            selected: Color::Rgb(0x33, 0xa0, 0x33),
+            legal_move: Color::Rgb(0x33, 0xa0, 0x33),
+            last_move: Color::Rgb(0xcd, 0xc2, 0x6a),
+            check: Color::Rgb(0xd9, 0x41, 0x3a),
+            border: Color::Rgb(0x33, 0xa0, 0x33),
+            title: Color::DarkGray,
+            warning: Color::Red,
+            disabled: Color::DarkGray,
+        }
+    }
+}
+
+/// How many colours the terminal can show.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// any RGB colour
+    #[default]
+    TrueColor,
+    /// the xterm 256-colour palette
+    Indexed,
+    /// the 16 ANSI colours
+    Ansi,
+}
+
+impl ColorDepth {
+    /// Guesses the depth from `COLORTERM` and `TERM`. Terminals that do not
+    /// announce 256 colours get the 16 everyone has; without `TERM` at all,
+    /// as on Windows, RGB is assumed.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default().to_ascii_lowercase();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        match std::env::var("TERM") {
+            Ok(term) if term.contains("direct") => ColorDepth::TrueColor,
+            Ok(term) if term.contains("256") => ColorDepth::Indexed,
+            Ok(term) if !term.is_empty() => ColorDepth::Ansi,
+            _ => ColorDepth::TrueColor,
+        }
+    }
+}
+
+impl FromStr for ColorDepth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            "256" => Ok(ColorDepth::Indexed),
+            "16" | "ansi" => Ok(ColorDepth::Ansi),
+            _ => anyhow::bail!("colour depth is truecolor, 256 or 16, not `{s}`"),
+        }
+    }
+}
+
+/// The 16 ANSI colours with their usual xterm values.
+const ANSI: [(Color, [u8; 3]); 16] = [
+    (Color::Black, [0, 0, 0]),
+    (Color::Red, [205, 0, 0]),
+    (Color::Green, [0, 205, 0]),
+    (Color::Yellow, [205, 205, 0]),
+    (Color::Blue, [0, 0, 238]),
+    (Color::Magenta, [205, 0, 205]),
+    (Color::Cyan, [0, 205, 205]),
+    (Color::Gray, [229, 229, 229]),
+    (Color::DarkGray, [127, 127, 127]),
+    (Color::LightRed, [255, 0, 0]),
+    (Color::LightGreen, [0, 255, 0]),
+    (Color::LightYellow, [255, 255, 0]),
+    (Color::LightBlue, [92, 92, 255]),
+    (Color::LightMagenta, [255, 0, 255]),
+    (Color::LightCyan, [0, 255, 255]),
+    (Color::White, [255, 255, 255]),
+];
+
+/// Colours apart by less than this are hard to tell from each other.
+const MIN_CONTRAST: u32 = 64;
+
+/// RGB value of `color`, or `None` for the terminal's default.
+fn rgb(color: Color) -> Option<[u8; 3]> {
+    // steps of the 6x6x6 cube of the 256-colour palette
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match color {
+        Color::Reset => None,
+        Color::Rgb(r, g, b) => Some([r, g, b]),
+        Color::Indexed(index @ 0..=15) => Some(ANSI[usize::from(index)].1),
+        Color::Indexed(index @ 16..=231) => {
+            let index = index - 16;
+            Some([CUBE[usize::from(index / 36)], CUBE[usize::from(index / 6 % 6)], CUBE[usize::from(index % 6)]])
+        }
+        Color::Indexed(index) => {
+            let level = 8 + 10 * (index - 232);
+            Some([level, level, level])
+        }
+        named => ANSI.iter().find(|(ansi, _)| *ansi == named).map(|&(_, rgb)| rgb),
+    }
+}
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter().zip(b).map(|(&a, b)| u32::from(a.abs_diff(b)).pow(2)).sum::<u32>().isqrt()
+}
+
+impl Palette {
+    /// The palette as close as `depth` allows. Square, highlight and piece
+    /// colours are kept apart from each other, even where that means
+    /// straying further from the theme.
+    pub fn reduce(self, depth: ColorDepth) -> Self {
+        let candidates: Vec<(Color, [u8; 3])> = match depth {
+            ColorDepth::TrueColor => return self,
+            ColorDepth::Indexed => (16..=255).map(|index| (Color::Indexed(index), rgb(Color::Indexed(index)).unwrap())).collect(),
+            ColorDepth::Ansi => ANSI.to_vec(),
+        };
+        // colours the terminal has anyway are left alone
+        let keeps = |color: Color| match color {
+            Color::Rgb(..) => false,
+            Color::Indexed(index) => depth == ColorDepth::Indexed || index < 16,
+            _ => true,
+        };
+        let pick = |color: Color, avoid: &[Color]| -> Color {
+            let Some(target) = rgb(color).filter(|_| !keeps(color)) else {
+                return color;
+            };
+            let avoid: Vec<_> = avoid.iter().filter_map(|&color| rgb(color)).collect();
+            let nearest = |far_enough: &dyn Fn([u8; 3]) -> bool| {
+                candidates
+                    .iter()
+                    .filter(|&&(_, candidate)| far_enough(candidate))
+                    .min_by_key(|&&(_, candidate)| distance(candidate, target))
+                    .map(|&(color, _)| color)
+            };
+            nearest(&|candidate| avoid.iter().all(|&other| distance(candidate, other) >= MIN_CONTRAST))
+                .or_else(|| nearest(&|candidate| !avoid.contains(&candidate)))
+                .or_else(|| nearest(&|_| true))
+                .unwrap_or(color)
+        };
+
+        let white_piece = pick(self.white_piece, &[]);
+        let black_piece = pick(self.black_piece, &[white_piece]);
+        let light_square = pick(self.light_square, &[white_piece, black_piece]);
+        let dark_square = pick(self.dark_square, &[white_piece, black_piece, light_square]);
+        let mut backgrounds = vec![white_piece, black_piece, light_square, dark_square];
+        let selected = pick(self.selected, &backgrounds);
+        backgrounds.push(selected);
+        let last_move = pick(self.last_move, &backgrounds);
+        backgrounds.push(last_move);
+        let check = pick(self.check, &backgrounds);
+        // legal move markers sit on the squares and next to every highlight
+        let legal_move = pick(self.legal_move, &[light_square, dark_square, selected, last_move, check]);
+        Self {
+            light_square,
+            dark_square,
+            white_piece,
+            black_piece,
+            selected,
+            legal_move,
+            last_move,
+            check,
+            border: pick(self.border, &[]),
+            title: pick(self.title, &[]),
+            warning: pick(self.warning, &[]),
+            disabled: pick(self.disabled, &[]),
+        }
+    }
+}
+
+/// Reads a colour by name, e.g. `darkgray`, as `#rrggbb` or as a 256-colour index.
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Color::from_str(&name).map_err(|_| serde::de::Error::custom(format!("invalid colour `{name}`")))
+}
+
+/// A named set of colours.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// file name without the extension, used to pick the theme
+    pub id: String,
+    /// name shown when switching themes
+    pub name: String,
+    pub colors: Palette,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    #[serde(default)]
+    colors: Palette,
+}
+
+impl Theme {
+    /// Parses a theme file called `id`.
+    pub fn parse(id: &str, text: &str) -> Result<Self> {
+        let file: ThemeFile = toml::from_str(text)?;
+        Ok(Self {
+            id: id.to_owned(),
+            name: file.name.unwrap_or_else(|| id.to_owned()),
+            colors: file.colors,
+        })
+    }
+
+    /// Whether `name` picks this theme, by id or name in any case.
+    pub fn is_called(&self, name: &str) -> bool {
+        self.id.eq_ignore_ascii_case(name) || self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+/// The built-in themes, followed by those in the `themes` directory of the
+/// configuration directory. A user theme with the id of a built-in one
+/// replaces it.
+pub fn load_all() -> Result<Vec<Theme>> {
+    let mut themes = BUILT_IN
+        .iter()
+        .map(|(id, text)| Theme::parse(id, text).with_context(|| format!("built-in theme {id}")))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(dir) = config_dir() {
+        for theme in load_dir(&dir.join("themes"))? {
+            match themes.iter_mut().find(|known| known.id == theme.id) {
+                Some(known) => *known = theme,
+                None => themes.push(theme),
+            }
+        }
+    }
+    Ok(themes)
+}
+
+/// The `*.toml` themes in `dir`, sorted by file name, or none if it does not exist.
+fn load_dir(dir: &Path) -> Result<Vec<Theme>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let id = path.file_stem().unwrap_or_default().to_string_lossy();
+            let text = fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+            Theme::parse(&id, &text).with_context(|| format!("invalid theme {}", path.display()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_theme_file_is_built_in() {
+        let mut files: Vec<_> = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/themes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path().file_stem().unwrap().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        let mut built_in: Vec<_> = BUILT_IN.iter().map(|(id, _)| id.to_string()).collect();
+        built_in.sort();
+        assert_eq!(files, built_in);
+    }
+
+    #[test]
+    fn reduced_board_colours_stay_distinct() {
+        for (id, text) in BUILT_IN {
+            let theme = Theme::parse(id, text).unwrap();
+            for depth in [ColorDepth::Indexed, ColorDepth::Ansi] {
+                let palette = theme.colors.reduce(depth);
+                let colors = [
+                    ("light_square", palette.light_square),
+                    ("dark_square", palette.dark_square),
+                    ("selected", palette.selected),
+                    ("legal_move", palette.legal_move),
+                    ("last_move", palette.last_move),
+                    ("check", palette.check),
+                ];
+                for (i, (name, color)) in colors.iter().enumerate() {
+                    for (other, other_color) in &colors[i + 1..] {
+                        assert_ne!(color, other_color, "{id} at {depth:?}: {name} and {other} are both {color:?}");
+                    }
+                }
+                // pieces stand out from the squares they stand on
+                for piece in [palette.white_piece, palette.black_piece] {
+                    assert_ne!(piece, palette.light_square, "{id} at {depth:?}");
+                    assert_ne!(piece, palette.dark_square, "{id} at {depth:?}");
+                }
+            }
+        }
+    }
+}
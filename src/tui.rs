@@ -2,7 +2,7 @@ use std::{io, panic};
 
 use anyhow::Result;
 use crossterm::{
-  event::{DisableMouseCapture, EnableMouseCapture},
+  event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
   terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
@@ -33,7 +33,7 @@ impl Tui {
   /// It enables the raw mode and sets terminal properties.
   pub fn enter(&mut self) -> Result<()> {
     terminal::enable_raw_mode()?;
-    crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+    crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
 
     // Define a custom panic hook to reset the terminal properties.
     // This way, you won't have your terminal messed up if an unexpected error happens.
@@ -65,7 +65,7 @@ impl Tui {
   /// the terminal properties if unexpected errors occur.
   fn reset() -> Result<()> {
     terminal::disable_raw_mode()?;
-    crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+    crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste)?;
     Ok(())
   }
 
@@ -0,0 +1,532 @@
+//! Client for chess engines speaking the Universal Chess Interface.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chess::ChessMove;
+
+use crate::event::Event;
+
+/// How long the engine gets to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Evaluation reported by the engine, from the side to move's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// centipawns
+    Cp(i32),
+    /// mate in this many moves, negative when being mated
+    Mate(i32),
+}
+
+/// Search progress from an `info` line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    pub depth: Option<u32>,
+    /// 1-based line number when searching several lines
+    pub multipv: usize,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub pv: Vec<ChessMove>,
+}
+
+/// Something the engine said after the handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciMessage {
+    Info(Info),
+    /// best move, `None` when the position has no legal move
+    BestMove(Option<ChessMove>),
+    /// the engine process closed its output
+    Exited,
+}
+
+/// An `option` the engine advertised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineOption {
+    pub name: String,
+    pub kind: String,
+    pub default: Option<String>,
+}
+
+/// What to search for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Go {
+    Depth(u32),
+    MoveTime(Duration),
+    /// remaining time and increment of White then Black
+    Clock { wtime: Duration, btime: Duration, winc: Duration, binc: Duration },
+    /// until told to stop
+    Infinite,
+}
+
+/// A running engine process.
+///
+/// Messages are delivered as the event chosen at spawn. Every `go` ends with exactly one
+/// `bestmove`, so stopped searches are told apart by counting them.
+#[derive(Debug)]
+pub struct UciEngine {
+    /// name from `id name`
+    pub name: String,
+    pub options: Vec<EngineOption>,
+    child: Child,
+    stdin: ChildStdin,
+    /// searches whose `bestmove` has not arrived yet
+    searching: usize,
+    /// whether the result of the latest search is still wanted
+    wanted: bool,
+}
+
+impl UciEngine {
+    /// Starts the engine at `path`, runs the handshake and applies `options`.
+    ///
+    /// Later output is sent to `events`, wrapped by `event`.
+    pub fn spawn(
+        path: &Path,
+        args: &[String],
+        options: &[(String, String)],
+        events: mpsc::Sender<Event>,
+        event: fn(UciMessage) -> Event,
+    ) -> Result<Self> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to start engine {}", path.display()))?;
+        let stdin = child.stdin.take().context("engine has no stdin")?;
+        let stdout = child.stdout.take().context("engine has no stdout")?;
+
+        // lines are read on their own thread so the handshake can time out
+        let (lines, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if lines.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            name: path.display().to_string(),
+            options: Vec::new(),
+            child,
+            stdin,
+            searching: 0,
+            wanted: false,
+        };
+        engine.send("uci")?;
+        loop {
+            let line = receiver
+                .recv_timeout(HANDSHAKE_TIMEOUT)
+                .map_err(|_| anyhow!("{} did not answer `uci`", engine.name))?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("uciok") => break,
+                Some("id") if tokens.next() == Some("name") => {
+                    engine.name = tokens.collect::<Vec<_>>().join(" ");
+                }
+                Some("option") => engine.options.extend(parse_option(&line)),
+                _ => {}
+            }
+        }
+
+        for (name, value) in options {
+            engine.set_option(name, value)?;
+        }
+        engine.send("ucinewgame")?;
+        engine.send("isready")?;
+        loop {
+            let line = receiver
+                .recv_timeout(HANDSHAKE_TIMEOUT)
+                .map_err(|_| anyhow!("{} did not answer `isready`", engine.name))?;
+            if line.trim() == "readyok" {
+                break;
+            }
+        }
+
+        thread::spawn(move || {
+            for line in receiver {
+                if let Some(message) = parse_message(&line) {
+                    if events.send(event(message)).is_err() {
+                        return;
+                    }
+                }
+            }
+            let _ = events.send(event(UciMessage::Exited));
+        });
+        Ok(engine)
+    }
+
+    /// Whether the engine advertised the option `name`.
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|o| o.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Sets the advertised option `name`, matched regardless of case.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<()> {
+        let Some(option) = self.options.iter().find(|o| o.name.eq_ignore_ascii_case(name)) else {
+            bail!("{} has no option `{name}`", self.name);
+        };
+        let name = option.name.clone();
+        self.send(&format!("setoption name {name} value {value}"))
+    }
+
+    /// Searches the position reached from `fen` by `moves`.
+    pub fn go(&mut self, fen: &str, moves: &[ChessMove], go: Go) -> Result<()> {
+        self.stop()?;
+        let mut position = format!("position fen {fen}");
+        if !moves.is_empty() {
+            position.push_str(" moves");
+            for chess_move in moves {
+                position.push_str(&format!(" {chess_move}"));
+            }
+        }
+        self.send(&position)?;
+        self.send(&match go {
+            Go::Depth(depth) => format!("go depth {depth}"),
+            Go::MoveTime(time) => format!("go movetime {}", time.as_millis().max(1)),
+            Go::Clock { wtime, btime, winc, binc } => format!(
+                "go wtime {} btime {} winc {} binc {}",
+                wtime.as_millis(),
+                btime.as_millis(),
+                winc.as_millis(),
+                binc.as_millis()
+            ),
+            Go::Infinite => "go infinite".to_owned(),
+        })?;
+        self.searching += 1;
+        self.wanted = true;
+        Ok(())
+    }
+
+    /// Stops the running search and discards its result.
+    pub fn stop(&mut self) -> Result<()> {
+        if self.wanted {
+            self.wanted = false;
+            self.send("stop")?;
+        }
+        Ok(())
+    }
+
+    /// Whether the result of a search is awaited.
+    pub fn thinking(&self) -> bool {
+        self.wanted
+    }
+
+    /// Whether `message` belongs to the latest search, which is the only one still wanted.
+    ///
+    /// Must see every message, as `bestmove` ends a search.
+    pub fn accept(&mut self, message: &UciMessage) -> bool {
+        let current = self.wanted && self.searching == 1;
+        match message {
+            UciMessage::BestMove(_) => {
+                self.searching = self.searching.saturating_sub(1);
+                if current {
+                    self.wanted = false;
+                }
+                current
+            }
+            UciMessage::Info(_) => current,
+            UciMessage::Exited => {
+                self.searching = 0;
+                self.wanted = false;
+                true
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<()> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .with_context(|| format!("failed to talk to {}", self.name))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // give the engine a moment to exit by itself before killing it
+        for _ in 0..10 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads an `option name <name> type <type> [default <value>] ...` line.
+fn parse_option(line: &str) -> Option<EngineOption> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    // option names and values may contain spaces, so collect up to the next keyword
+    let field = |key: &str| {
+        let start = tokens.iter().position(|t| *t == key)? + 1;
+        let end = tokens[start..]
+            .iter()
+            .position(|t| ["name", "type", "default", "min", "max", "var"].contains(t))
+            .map_or(tokens.len(), |i| start + i);
+        Some(tokens[start..end].join(" "))
+    };
+    Some(EngineOption { name: field("name")?, kind: field("type")?, default: field("default") })
+}
+
+/// Reads a line the engine sent after the handshake.
+fn parse_message(line: &str) -> Option<UciMessage> {
+    let mut tokens = line.split_whitespace();
+    match tokens.next()? {
+        "bestmove" => {
+            let best = tokens.next()?;
+            Some(UciMessage::BestMove(ChessMove::from_str(best).ok()))
+        }
+        "info" => parse_info(tokens).map(UciMessage::Info),
+        _ => None,
+    }
+}
+
+/// Reads the fields of an `info` line, skipping lines without a score or PV
+/// such as `info string` and `info currmove`.
+fn parse_info<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Info> {
+    let mut info = Info { multipv: 1, ..Info::default() };
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => info.depth = tokens.next().and_then(|t| t.parse().ok()),
+            "multipv" => info.multipv = tokens.next().and_then(|t| t.parse().ok()).unwrap_or(1),
+            "nodes" => info.nodes = tokens.next().and_then(|t| t.parse().ok()),
+            "score" => {
+                info.score = match (tokens.next(), tokens.next().and_then(|t| t.parse().ok())) {
+                    (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                    (Some("mate"), Some(mate)) => Some(Score::Mate(mate)),
+                    _ => None,
+                }
+            }
+            // the principal variation runs to the end of the line
+            "pv" => info.pv = tokens.by_ref().map_while(|t| ChessMove::from_str(t).ok()).collect(),
+            "string" => return None,
+            _ => {}
+        }
+    }
+    (info.score.is_some() || !info.pv.is_empty()).then_some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use chess::{Board, Square};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// The stub engine from `examples/uci_stub.rs`, built next to the test binary.
+    fn stub() -> PathBuf {
+        let dir = std::env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_owned();
+        let path = dir.join("examples").join(format!("uci_stub{}", std::env::consts::EXE_SUFFIX));
+        if !path.exists() {
+            let status = Command::new(env!("CARGO"))
+                .args(["build", "--example", "uci_stub"])
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .status()
+                .unwrap();
+            assert!(status.success(), "failed to build the stub engine");
+        }
+        path
+    }
+
+    fn spawn(options: &[(String, String)]) -> (UciEngine, mpsc::Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        let engine = UciEngine::spawn(&stub(), &[], options, sender, Event::Uci).unwrap();
+        (engine, receiver)
+    }
+
+    fn next(events: &mpsc::Receiver<Event>) -> UciMessage {
+        match events.recv_timeout(HANDSHAKE_TIMEOUT).expect("the engine went quiet") {
+            Event::Uci(message) => message,
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+
+    fn info(line: &str) -> Option<Info> {
+        parse_info(line.split_whitespace())
+    }
+
+    #[test]
+    fn spawn_runs_the_handshake() {
+        let options = [("hash".to_owned(), "32".to_owned())];
+        let (engine, _events) = spawn(&options);
+        assert_eq!(engine.name, "uci_stub");
+        assert_eq!(engine.options.len(), 3);
+        assert_eq!(
+            engine.options[2],
+            EngineOption { name: "Skill Level".into(), kind: "spin".into(), default: Some("20".into()) }
+        );
+        assert!(engine.has_option("threads"));
+        assert!(!engine.thinking());
+    }
+
+    #[test]
+    fn spawn_rejects_unknown_options() {
+        let (sender, _events) = mpsc::channel();
+        let options = [("Ponder".to_owned(), "true".to_owned())];
+        let error = UciEngine::spawn(&stub(), &[], &options, sender, Event::Uci).unwrap_err();
+        assert_eq!(error.to_string(), "uci_stub has no option `Ponder`");
+        let (sender, _events) = mpsc::channel();
+        assert!(UciEngine::spawn(Path::new("/nonexistent/engine"), &[], &[], sender, Event::Uci).is_err());
+    }
+
+    #[test]
+    fn go_ends_with_bestmove() {
+        let (mut engine, events) = spawn(&[]);
+        let e2e4 = ChessMove::new(Square::E2, Square::E4, None);
+        engine.go(START, &[e2e4], Go::Depth(1)).unwrap();
+        assert!(engine.thinking());
+
+        let message = next(&events);
+        let UciMessage::Info(ref line) = message else { panic!("expected info, got {message:?}") };
+        assert_eq!(line.depth, Some(1));
+        assert_eq!(line.score, Some(Score::Cp(0)));
+        assert!(engine.accept(&message));
+
+        let message = next(&events);
+        let UciMessage::BestMove(Some(best)) = message else { panic!("expected bestmove, got {message:?}") };
+        assert_eq!(line.pv, [best]);
+        assert!(Board::from_str(START).unwrap().make_move_new(e2e4).legal(best));
+        assert!(engine.accept(&message));
+        assert!(!engine.thinking());
+    }
+
+    #[test]
+    fn go_reports_positions_without_moves() {
+        let (mut engine, events) = spawn(&[]);
+        engine.go("k7/8/1QK5/8/8/8/8/8 b - - 0 1", &[], Go::MoveTime(Duration::ZERO)).unwrap();
+        let message = next(&events);
+        assert_eq!(message, UciMessage::BestMove(None));
+        assert!(engine.accept(&message));
+    }
+
+    #[test]
+    fn stopped_searches_are_discarded() {
+        let (mut engine, events) = spawn(&[]);
+        engine.go(START, &[], Go::Infinite).unwrap();
+        engine.stop().unwrap();
+        assert!(!engine.thinking());
+        // the stopped search still answers, but nobody wants it
+        for _ in 0..2 {
+            assert!(!engine.accept(&next(&events)));
+        }
+        // stopping twice sends nothing
+        engine.stop().unwrap();
+        assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn only_the_latest_search_is_accepted() {
+        let (mut engine, events) = spawn(&[]);
+        engine.go(START, &[], Go::Infinite).unwrap();
+        // a new search stops the old one, whose info and bestmove arrive first
+        engine.go(START, &[], Go::Depth(1)).unwrap();
+        assert!(!engine.accept(&next(&events)));
+        let message = next(&events);
+        assert!(matches!(message, UciMessage::BestMove(Some(_))));
+        assert!(!engine.accept(&message));
+        assert!(engine.thinking());
+
+        assert!(engine.accept(&next(&events)));
+        let message = next(&events);
+        assert!(matches!(message, UciMessage::BestMove(Some(_))));
+        assert!(engine.accept(&message));
+        assert!(!engine.thinking());
+    }
+
+    #[test]
+    fn exit_ends_every_search() {
+        let (mut engine, events) = spawn(&[]);
+        engine.go(START, &[], Go::Infinite).unwrap();
+        engine.send("quit").unwrap();
+        let message = loop {
+            match next(&events) {
+                UciMessage::Info(_) => {}
+                message => break message,
+            }
+        };
+        assert_eq!(message, UciMessage::Exited);
+        assert!(engine.accept(&message));
+        assert!(!engine.thinking());
+    }
+
+    #[test]
+    fn parse_info_reads_the_fields() {
+        let line = "depth 12 seldepth 18 multipv 2 score cp -35 nodes 123456 nps 1000 pv e2e4 e7e5 g1f3";
+        let info = info(line).unwrap();
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.multipv, 2);
+        assert_eq!(info.score, Some(Score::Cp(-35)));
+        assert_eq!(info.nodes, Some(123456));
+        let pv: Vec<String> = info.pv.iter().map(ToString::to_string).collect();
+        assert_eq!(pv, ["e2e4", "e7e5", "g1f3"]);
+
+        let info = self::info("depth 5 score mate -3 pv h7h8q").unwrap();
+        assert_eq!(info.score, Some(Score::Mate(-3)));
+        assert_eq!(info.multipv, 1);
+        assert_eq!(info.pv, [ChessMove::new(Square::H7, Square::H8, Some(chess::Piece::Queen))]);
+    }
+
+    #[test]
+    fn parse_info_skips_malformed_lines() {
+        assert_eq!(info("string score cp 10 pv e2e4"), None);
+        assert_eq!(info("depth 3 currmove e2e4 currmovenumber 1"), None);
+        assert_eq!(info(""), None);
+        assert_eq!(info("score cp"), None);
+        assert_eq!(info("score wdl 500 300 200"), None);
+        // bad numbers are dropped, the rest of the line is kept
+        let info = info("depth x multipv y score cp 12 nodes -1").unwrap();
+        assert_eq!((info.depth, info.multipv, info.nodes), (None, 1, None));
+        assert_eq!(info.score, Some(Score::Cp(12)));
+        // the PV stops at the first token that is no move
+        let info = self::info("pv e2e4 xyzzy e7e5").unwrap();
+        assert_eq!(info.pv.len(), 1);
+    }
+
+    #[test]
+    fn parse_message_reads_bestmove() {
+        let e2e4 = ChessMove::new(Square::E2, Square::E4, None);
+        assert_eq!(parse_message("bestmove e2e4 ponder e7e5"), Some(UciMessage::BestMove(Some(e2e4))));
+        assert_eq!(parse_message("bestmove (none)"), Some(UciMessage::BestMove(None)));
+        assert_eq!(parse_message("bestmove"), None);
+        assert_eq!(parse_message("readyok"), None);
+        assert_eq!(parse_message("   "), None);
+    }
+
+    #[test]
+    fn parse_option_reads_names_with_spaces() {
+        let option = parse_option("option name Skill Level type spin default 20 min 0 max 20").unwrap();
+        assert_eq!(option.name, "Skill Level");
+        assert_eq!(option.kind, "spin");
+        assert_eq!(option.default.as_deref(), Some("20"));
+
+        let option = parse_option("option name Style type combo default Normal var Solid var Normal").unwrap();
+        assert_eq!(option.default.as_deref(), Some("Normal"));
+        let option = parse_option("option name Clear Hash type button").unwrap();
+        assert_eq!((&option.name[..], &option.kind[..], option.default), ("Clear Hash", "button", None));
+        // an empty default is still a default
+        let option = parse_option("option name NalimovPath type string default").unwrap();
+        assert_eq!(option.default.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn parse_option_rejects_malformed_lines() {
+        assert_eq!(parse_option("option"), None);
+        assert_eq!(parse_option("option type spin default 1"), None);
+        assert_eq!(parse_option("option name Hash default 16"), None);
+    }
+}
@@ -1,32 +1,400 @@
+use std::rc::Rc;
+use std::time::Instant;
+
+use chess::{BoardStatus, Color as Side};
 use ratatui::{
     layout::{Alignment, Direction},
-    style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Borders, Paragraph},
-    prelude::{Constraint, Layout, Rect, Line},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap},
+    prelude::{Constraint, Layout, Rect},
 };
 
-use crate::app::App;
+use crate::analysis::{format_score, white_share, EvalBar};
+use crate::app::{App, GameAction, PROMOTION_PIECES};
+use crate::clock::format_time;
+use crate::game::MIN_BOARD;
+use crate::keymap::Action;
+use crate::material;
+use crate::outcome::color_name;
+use crate::san;
+use crate::theme::Palette;
 use crate::tui::Frame;
 
-pub fn render(app: &mut App, f: &mut Frame) {
-    let layout = Layout::default()
+/// Rounded panel with a centred title, as used throughout the interface.
+fn panel<'a>(palette: &Palette, title: &'a str) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::new().fg(palette.border))
+        .title(title)
+        .title_style(Style::new().fg(palette.title))
+        .title_alignment(Alignment::Center)
+}
+
+/// Pieces `side` has captured, followed by its material lead if it has one.
+fn captures(app: &App, side: Side) -> String {
+    let board = app.history.position();
+    let glyphs = app.game_state.glyphs;
+    let mut text: String = material::captured(&app.history, !side)
+        .into_iter()
+        .map(|piece| glyphs.piece(piece, !side))
+        .collect();
+    let lead = match side {
+        Side::White => material::balance(&board),
+        Side::Black => -material::balance(&board),
+    };
+    if lead > 0 {
+        text.push_str(&format!(" +{lead}"));
+    }
+    text
+}
+
+/// Panel with `side`'s clock, or a turn marker when playing without clocks,
+/// and the pieces it has captured.
+fn render_player(app: &App, side: Side, f: &mut Frame, area: Rect) {
+    let to_move = app.outcome.is_none() && app.game.0.side_to_move() == side;
+    let (text, mut style) = match &app.clock {
+        Some(clock) => {
+            let left = clock.remaining(side, Instant::now());
+            let style = if left.as_secs() < 10 {
+                Style::new().fg(app.palette().warning)
+            } else {
+                Style::new()
+            };
+            (format_time(left), style)
+        }
+        None if to_move => ("to move".to_owned(), Style::new()),
+        None => (String::new(), Style::new()),
+    };
+    if to_move {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    let title = match &app.computer {
+        Some(computer) if computer.color == side => format!(" {} (computer) ", color_name(side)),
+        _ => format!(" {} ", color_name(side)),
+    };
+    let text = vec![Line::styled(text, style), Line::from(captures(app, side))];
+    f.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(panel(&app.palette(), &title)),
+        area,
+    );
+}
+
+/// Lines of the analysis, best first, for the side panel.
+fn analysis_text(app: &App) -> String {
+    let Some(analysis) = &app.analysis else {
+        return String::new();
+    };
+    let board = app.history.position();
+    match board.status() {
+        BoardStatus::Checkmate => return "Checkmate".to_owned(),
+        BoardStatus::Stalemate => return "Stalemate".to_owned(),
+        BoardStatus::Ongoing => {}
+    }
+    let Some(best) = analysis.lines.first() else {
+        return "Thinking...".to_owned();
+    };
+    let number = app.history.move_number_at(app.history.current());
+    let mut text = format!("depth {}\n", best.depth);
+    for line in &analysis.lines {
+        text.push_str(&format!("\n{} {}\n", format_score(line.score), line.san(&board, number)));
+    }
+    text
+}
+
+/// Buttons for the actions open to the player, remembered for mouse clicks.
+fn render_actions(app: &mut App, f: &mut Frame, area: Rect) {
+    let palette = app.palette();
+    let block = panel(&palette, " Actions ");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    app.buttons.clear();
+    for (action, y) in app.available_actions().into_iter().zip((inner.top()..inner.bottom()).step_by(2)) {
+        let (key, label) = match action {
+            GameAction::Resign if app.confirm_resign => (Action::Resign, "Confirm resign"),
+            GameAction::Resign => (Action::Resign, "Resign"),
+            GameAction::OfferDraw => (Action::OfferDraw, "Offer draw"),
+            GameAction::AcceptDraw => (Action::AcceptDraw, "Accept draw"),
+            GameAction::DeclineDraw => (Action::DeclineDraw, "Decline draw"),
+            GameAction::ClaimDraw => (Action::ClaimDraw, "Claim draw"),
+        };
+        let label = format!("[{}] {label}", app.keymap.hint(key));
+        let style = match action {
+            GameAction::ClaimDraw if !app.history.can_claim_draw() => Style::new().fg(palette.disabled),
+            GameAction::Resign if app.confirm_resign => Style::new().fg(palette.warning).add_modifier(Modifier::BOLD),
+            _ => Style::new(),
+        };
+        let button = Rect::new(inner.x, y, inner.width, 1);
+        f.render_widget(Paragraph::new(label).style(style), button);
+        app.buttons.push((button, action));
+    }
+}
+
+/// Banner over the board announcing the result, with what can be done next.
+fn render_banner(app: &App, f: &mut Frame, area: Rect) {
+    let Some(outcome) = &app.outcome else {
+        return;
+    };
+    let key = |action| app.keymap.hint(action);
+    let text = format!(
+        "{}\n\n[{}] New game\n[{}] Rematch, colours swapped\n[{}] Review the game\n[{}] Close",
+        outcome.describe(),
+        key(Action::NewGame),
+        key(Action::Rematch),
+        key(Action::Review),
+        key(Action::Back),
+    );
+    let width = 36.min(area.width);
+    let height = 8.min(area.height);
+    let banner = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    f.render_widget(Clear, banner);
+    f.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .block(panel(&app.palette(), " Game over ")),
+        banner,
+    );
+}
+
+/// Every move of the game, the one leading to the position on the board
+/// highlighted and kept in view. Each move is remembered for mouse clicks.
+fn render_moves(app: &mut App, palette: &Palette, f: &mut Frame, area: Rect) {
+    let block = panel(palette, " Moves ");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    app.move_buttons.clear();
+
+    let start = app.history.start();
+    let pairs = san::numbered_pairs(app.history.sans(), start.side_to_move(), app.history.first_move_number());
+    let mut lines = Vec::new();
+    // row and column of each move, with the ply after it
+    let mut entries = Vec::new();
+    let mut current_row = 0;
+    let mut ply = 0;
+    for pair in &pairs {
+        // a move number, then the moves of one or both sides
+        let mut tokens = pair.split(' ');
+        let number = tokens.next().unwrap_or_default();
+        let mut spans = vec![Span::raw(number)];
+        let mut x = number.len();
+        for san in tokens {
+            ply += 1;
+            let style = if ply == app.history.current() {
+                current_row = lines.len();
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::new()
+            };
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(san, style));
+            entries.push((lines.len(), x + 1, san.len(), ply));
+            x += 1 + san.len();
+        }
+        lines.push(Line::from(spans));
+    }
+
+    let height = usize::from(inner.height);
+    let scroll = (current_row + 1).saturating_sub(height);
+    for (row, x, width, ply) in entries {
+        let x = x as u16;
+        if (scroll..scroll + height).contains(&row) && x < inner.width {
+            let width = (width as u16).min(inner.width - x);
+            app.move_buttons.push((Rect::new(inner.x + x, inner.y + (row - scroll) as u16, width, 1), ply));
+        }
+    }
+    f.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)), inner);
+}
+
+/// Columns taken from the board panel for the evaluation bar and the gap beside it.
+const EVAL_BAR_WIDTH: u16 = 4;
+
+/// Tile sizes of the promotion popup, largest first.
+const PROMOTION_TILES: [(u16, u16); 3] = [(9, 5), (7, 3), (3, 1)];
+
+/// Popup over the board asking which piece a pawn promotes to. Each piece's
+/// tile is remembered for mouse clicks.
+fn render_promotion(app: &mut App, f: &mut Frame, area: Rect) {
+    let palette = app.palette();
+    let glyphs = app.game_state.glyphs;
+    let keys = [Action::Queen, Action::Rook, Action::Bishop, Action::Knight].map(|action| app.keymap.hint(action));
+    let names = ["Queen", "Rook", "Bishop", "Knight"];
+    let board = app.game.0.current_position();
+    let Some(promotion) = &mut app.promotion else {
+        return;
+    };
+    let color = board.color_on(promotion.from).unwrap_or(Side::White);
+
+    // four tiles side by side with their keys below, in a bordered panel
+    let (width, height) = PROMOTION_TILES
+        .into_iter()
+        .find(|&(width, height)| 4 * width + 2 <= area.width && height + 3 <= area.height)
+        .unwrap_or(PROMOTION_TILES[2]);
+    let popup_width = (4 * width + 2).min(area.width);
+    let popup_height = (height + 3).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - popup_width) / 2,
+        area.y + (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+    f.render_widget(Clear, popup);
+    let block = panel(&palette, " Promote to ");
+    let inner = block.inner(popup);
+    f.render_widget(block, popup);
+    promotion.area = popup;
+    promotion.tiles.clear();
+
+    // the key picking each piece, and its name if all of them fit
+    let labelled = keys.iter().zip(names).map(|(key, name)| format!("{key} {name}"));
+    let labels: Vec<String> = if labelled.clone().all(|label| label.chars().count() <= usize::from(width)) {
+        labelled.collect()
+    } else {
+        keys.to_vec()
+    };
+
+    let buf = f.buffer_mut();
+    for (index, (piece, label)) in PROMOTION_PIECES.into_iter().zip(labels).enumerate() {
+        let tile = Rect::new(inner.x + index as u16 * width, inner.y, width, height).intersection(inner);
+        if tile.is_empty() {
+            continue;
+        }
+        let background = if index == promotion.choice {
+            palette.selected
+        } else if index % 2 == 0 {
+            palette.light_square
+        } else {
+            palette.dark_square
+        };
+        buf.set_style(tile, Style::new().bg(background));
+        let fg_color = if color == Side::White { palette.white_piece } else { palette.black_piece };
+        let (x, y) = (tile.x + tile.width / 2, tile.y + tile.height / 2);
+        match glyphs.picture(piece, color).filter(|_| tile.width >= 5 && tile.height >= 3) {
+            Some(picture) => {
+                for (line, y) in picture.into_iter().zip(y - 1..) {
+                    for (c, x) in line.chars().zip(x - 1..) {
+                        buf.get_mut(x, y).set_char(c).set_fg(fg_color);
+                    }
+                }
+            }
+            None => {
+                buf.get_mut(x, y).set_char(glyphs.piece(piece, color)).set_fg(fg_color);
+            }
+        }
+
+        let label_width = (label.chars().count() as u16).min(width);
+        let mut style = Style::new();
+        if index == promotion.choice {
+            style = style.add_modifier(Modifier::BOLD | Modifier::REVERSED);
+        }
+        if tile.bottom() < inner.bottom() {
+            buf.set_stringn(tile.x + (width - label_width) / 2, tile.bottom(), &label, usize::from(width), style);
+        }
+        promotion.tiles.push((Rect::new(tile.x, tile.y, tile.width, tile.height + 1).intersection(inner), piece));
+    }
+}
+
+/// Rows of the interface: players and info, the board, players and status.
+fn rows(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![
                      Constraint::Percentage(12),
                      Constraint::Percentage(76),
                      Constraint::Percentage(12),
         ])
-        .split(f.size());
-    let cols = Layout::default()
+        .split(area)
+}
+
+/// Columns of the middle row: side panel, board and move list.
+fn columns(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Horizontal)
         .constraints(vec![
                      Constraint::Percentage(20),
                      Constraint::Percentage(60),
                      Constraint::Percentage(20),
         ])
-        .split(layout[1]);
+        .split(area)
+}
+
+/// Smallest terminal the board fits in.
+fn minimum_size() -> (u16, u16) {
+    // the board panel has a border on each side
+    let fits = |width: u16, height: u16| {
+        let board = columns(rows(Rect::new(0, 0, width, height))[1])[1];
+        board.width >= MIN_BOARD.0 + 2 && board.height >= MIN_BOARD.1 + 2
+    };
+    // rows and columns are laid out independently, so each is found alone
+    let width = (1..=300).find(|&width| fits(width, 200)).unwrap_or(300);
+    let height = (1..=200).find(|&height| fits(300, height)).unwrap_or(200);
+    (width, height)
+}
 
-    f.render_stateful_widget(app.game.clone(), cols[1], &mut app.game_state);
+pub fn render(app: &mut App, f: &mut Frame) {
+    let layout = rows(f.size());
+    let cols = columns(layout[1]);
+
+    // Nothing else is worth showing without room for the board
+    let palette = app.palette();
+    let mut board_area = panel(&palette, " Board ").inner(cols[1]);
+    if board_area.width < MIN_BOARD.0 || board_area.height < MIN_BOARD.1 {
+        app.buttons.clear();
+        app.move_buttons.clear();
+        if let Some(promotion) = &mut app.promotion {
+            promotion.area = Rect::default();
+            promotion.tiles.clear();
+        }
+        app.game_state.board = Rect::default();
+        app.game_state.squares = Default::default();
+        let (width, height) = minimum_size();
+        let message = format!("Terminal too small, need {width}x{height}");
+        let area = f.size();
+        f.render_widget(
+            Paragraph::new(message).alignment(Alignment::Center).wrap(Wrap { trim: true }),
+            Rect::new(area.x, area.y + area.height / 2, area.width, area.height - area.height / 2),
+        );
+        return;
+    }
+
+    // The evaluation bar goes left of the board, which is fitted to what remains
+    let eval_bar = app.analysis.is_some() && board_area.width >= MIN_BOARD.0 + EVAL_BAR_WIDTH;
+    if eval_bar {
+        board_area.x += EVAL_BAR_WIDTH;
+        board_area.width -= EVAL_BAR_WIDTH;
+    }
+    app.game_state.flipped = app.bottom_side() == Side::Black;
+    app.game_state.palette = palette;
+    f.render_stateful_widget(app.game.clone(), board_area, &mut app.game_state);
+
+    // Evaluation bar left of the board, where the board was drawn
+    if let Some(analysis) = app.analysis.as_ref().filter(|_| eval_bar) {
+        let board = app.game_state.board;
+        if !board.is_empty() {
+            let share = match app.history.position().status() {
+                // the side to move is mated
+                BoardStatus::Checkmate if app.game.0.side_to_move() == Side::White => 0.0,
+                BoardStatus::Checkmate => 1.0,
+                _ => analysis.lines.first().map_or(0.5, |line| white_share(line.score)),
+            };
+            let bar = EvalBar {
+                white_share: share,
+                flipped: app.game_state.flipped,
+                white: palette.white_piece,
+                black: palette.black_piece,
+            };
+            f.render_widget(bar, Rect::new(board.x - 3, board.y, 2, board.height));
+        }
+    }
     //f.render_widget(Paragraph::new(vec![
     //        Line::from("Title"),
     //        Line::from("Horizontal Layout Example. Press q to quit"),
@@ -35,40 +403,61 @@ pub fn render(app: &mut App, f: &mut Frame) {
     //        Line::from("Note: constraint labels that don't fit are truncated"),                    
     //]).alignment(Alignment::Center), int_cols[0]);
 
-    f.render_widget(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded)
-            .border_style(Style::new().fg(Color::Rgb(0x33, 0xa0, 0x33)))
-            .title(" Top ")
-            .title_style(Style::new().fg(Color::DarkGray))
-            .title_alignment(Alignment::Center), layout[0]);
-    f.render_widget(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded)
-            .border_style(Style::new().fg(Color::Rgb(0x33, 0xa0, 0x33)))
-            .title(" Board ")
-            .title_style(Style::new().fg(Color::DarkGray))
-            .title_alignment(Alignment::Center), cols[1]);
-    f.render_widget(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded)
-            .border_style(Style::new().fg(Color::Rgb(0x33, 0xa0, 0x33)))
-            .title(" Left ")
-            .title_style(Style::new().fg(Color::DarkGray))
-            .title_alignment(Alignment::Center), cols[0]);
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Length(20), Constraint::Min(0)])
+        .split(layout[0]);
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Length(20), Constraint::Min(0)])
+        .split(layout[2]);
+
+    // Clocks, each side on its own edge of the board
+    let bottom_side = app.bottom_side();
+    render_player(app, !bottom_side, f, top[0]);
+    render_player(app, bottom_side, f, bottom[0]);
+
+    let to_move = format!("{} to move", color_name(app.game.0.side_to_move()));
+    let mut info = match &app.outcome {
+        _ if app.analysis.is_some() => format!("Analysis | {to_move}"),
+        Some(outcome) => outcome.describe(),
+        None if app.computer.as_ref().is_some_and(|c| c.thinking()) => "Computer is thinking...".to_owned(),
+        None => to_move,
+    };
+    if let Some(clock) = &app.clock {
+        info = format!("{info} | {}", clock.control);
+    }
+    f.render_widget(Paragraph::new(info).alignment(Alignment::Center).block(panel(&palette, " tchess ")), top[1]);
+    f.render_widget(panel(&palette, " Board "), cols[1]);
+    if app.banner {
+        render_banner(app, f, cols[1]);
+    }
+    if app.promotion.is_some() {
+        render_promotion(app, f, cols[1]);
+    }
+    if app.analysis.is_some() {
+        app.buttons.clear();
+        f.render_widget(
+            Paragraph::new(analysis_text(app))
+                .wrap(Wrap { trim: true })
+                .block(panel(&palette, " Analysis ")), cols[0]);
+    } else {
+        render_actions(app, f, cols[0]);
+    }
+
+    render_moves(app, &palette, f, cols[2]);
+    let status = match &app.viewer {
+        Some(viewer) if app.analysis.is_none() => viewer.describe(app.history.current()).join("\n"),
+        _ => app.status.clone().unwrap_or_default(),
+    };
+    let status = match app.draw_offer {
+        Some(offerer) => format!("{} offers a draw, awaiting an answer\n{status}", color_name(offerer)),
+        None => status,
+    };
     f.render_widget(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_type(BorderType::Rounded)
-            .border_style(Style::new().fg(Color::Rgb(0x33, 0xa0, 0x33)))
-            .title(" Right ")
-            .title_style(Style::new().fg(Color::DarkGray))
-            .title_alignment(Alignment::Center), cols[2]);
-    f.render_widget(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded)
-            .border_style(Style::new().fg(Color::Rgb(0x33, 0xa0, 0x33)))
-            .title(" Bottom ")
-            .title_style(Style::new().fg(Color::DarkGray))
-            .title_alignment(Alignment::Center), layout[2]);
+        Paragraph::new(status)
+            .wrap(Wrap { trim: true })
+            .block(panel(&palette, " Status ")), bottom[1]);
     //f.render_widget(
     //Paragraph::new(format!(
     //    "
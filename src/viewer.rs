@@ -0,0 +1,66 @@
+use crate::pgn::{PgnGame, PgnMove};
+
+/// Games opened from a PGN file for replaying.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub games: Vec<PgnGame>,
+    /// game shown on the board
+    pub index: usize,
+}
+
+impl Viewer {
+    /// The game shown on the board.
+    pub fn game(&self) -> &PgnGame {
+        &self.games[self.index]
+    }
+
+    /// Status lines for the game at `ply`, with the annotations of the move leading there.
+    pub fn describe(&self, ply: usize) -> Vec<String> {
+        let game = self.game();
+        let mut lines = vec![format!(
+            "Game {}/{}: {} vs {} ({}) | ply {}/{}",
+            self.index + 1,
+            self.games.len(),
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            game.result,
+            ply,
+            game.moves.len(),
+        )];
+
+        let comment = match ply {
+            0 => game.comment.clone(),
+            n => {
+                let last = &game.moves[n - 1];
+                if !last.nags.is_empty() {
+                    let nags: Vec<_> = last.nags.iter().map(|nag| nag_glyph(*nag)).collect();
+                    lines.push(format!("{} {}", last.san, nags.join(" ")));
+                }
+                if !last.variations.is_empty() {
+                    let variations: Vec<_> = last.variations.iter().map(|v| variation_text(v)).collect();
+                    lines.push(format!("Instead: ({})", variations.join(") (")));
+                }
+                last.comment.clone()
+            }
+        };
+        lines.extend(comment);
+        lines
+    }
+}
+
+/// Readable form of a numeric annotation glyph.
+fn nag_glyph(nag: u8) -> String {
+    match nag {
+        1 => "!".to_owned(),
+        2 => "?".to_owned(),
+        3 => "!!".to_owned(),
+        4 => "??".to_owned(),
+        5 => "!?".to_owned(),
+        6 => "?!".to_owned(),
+        n => format!("${n}"),
+    }
+}
+
+fn variation_text(moves: &[PgnMove]) -> String {
+    moves.iter().map(|m| m.san.as_str()).collect::<Vec<_>>().join(" ")
+}